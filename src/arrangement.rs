@@ -0,0 +1,165 @@
+use std::{fmt::Display, ops::Range};
+
+use m8_file_parser::{Note, Song, Step};
+
+/// Number of tracks in a song
+pub const TRACK_COUNT : usize = 8;
+
+/// Number of rows in the song view
+pub const SONG_ROW_COUNT : usize = 0x100;
+
+/// Value used by the M8 for an empty song/chain/phrase slot
+pub const EMPTY : u8 = 0xFF;
+
+/// Parse a row range like "0x10..0x20", "4..", "..8" or a single
+/// row, bounds can be written in hexadecimal with 0x prefix.
+pub fn parse_row_range(s: &str) -> Result<Range<usize>, String> {
+    fn bound(s: &str, default: usize) -> Result<usize, String> {
+        if s.is_empty() {
+            Ok(default)
+        } else {
+            clap_num::maybe_hex::<usize>(s)
+        }
+    }
+
+    let range = match s.split_once("..") {
+        None => {
+            let row = bound(s, 0)?;
+            row .. row + 1
+        }
+        Some((start, end)) => bound(start, 0)? .. bound(end, SONG_ROW_COUNT)?
+    };
+
+    if range.start >= range.end || range.end > SONG_ROW_COUNT {
+        Err(format!("Invalid row range {}", s))
+    } else {
+        Ok(range)
+    }
+}
+
+/// Chain referenced at a given song row & track, if any.
+pub fn chain_at(song: &Song, row: usize, track: usize) -> Option<usize> {
+    match song.song.steps[row * TRACK_COUNT + track] {
+        EMPTY => None,
+        c => Some(c as usize)
+    }
+}
+
+/// Last song row with a chain on any track
+pub fn last_used_row(song: &Song) -> Option<usize> {
+    (0 .. SONG_ROW_COUNT)
+        .rev()
+        .find(|row| (0 .. TRACK_COUNT).any(|t| chain_at(song, *row, t).is_some()))
+}
+
+/// Rows played by a track starting at a given row, the M8 stops
+/// playing a track at the first empty song row.
+pub fn played_rows(song: &Song, track: usize, start: usize) -> Range<usize> {
+    let end = (start .. SONG_ROW_COUNT)
+        .find(|row| chain_at(song, *row, track).is_none())
+        .unwrap_or(SONG_ROW_COUNT);
+
+    start .. end
+}
+
+/// Apply a chain transpose to a note, keeping empty notes.
+pub fn transpose_note(note: Note, transpose: u8) -> Note {
+    if note.0 == EMPTY {
+        note
+    } else {
+        Note(note.0.wrapping_add(transpose))
+    }
+}
+
+/// A phrase step reached by walking the song rows, then the chains
+/// and finally the phrases.
+pub struct ArrangedStep<'a> {
+    pub row: usize,
+    pub chain: usize,
+    pub chain_step: usize,
+    pub phrase: usize,
+    pub phrase_step: usize,
+
+    /// Transpose of the chain step
+    pub transpose: u8,
+    pub step: &'a Step
+}
+
+impl<'a> ArrangedStep<'a> {
+    /// Note of the step, with the chain transpose applied
+    pub fn note(&self) -> Note {
+        transpose_note(self.step.note, self.transpose)
+    }
+}
+
+/// Flatten all the steps played by a track, in song order.
+pub fn track_steps(song: &Song, track: usize, rows: Range<usize>) -> Vec<ArrangedStep<'_>> {
+    let mut steps = vec![];
+
+    for row in rows {
+        let Some(chain) = chain_at(song, row, track) else { continue };
+
+        for (chain_step, cs) in song.chains[chain].steps.iter().enumerate() {
+            if cs.phrase == EMPTY { continue }
+
+            let phrase = cs.phrase as usize;
+            for (phrase_step, step) in song.phrases[phrase].steps.iter().enumerate() {
+                steps.push(ArrangedStep {
+                    row,
+                    chain,
+                    chain_step,
+                    phrase,
+                    phrase_step,
+                    transpose: cs.transpose,
+                    step
+                });
+            }
+        }
+    }
+
+    steps
+}
+
+/// Display the flattened steps of some tracks, one line per
+/// step to be easily read or diffed.
+pub struct ArrangementView<'a> {
+    pub song: &'a Song,
+    pub tracks: Vec<usize>,
+    pub rows: Range<usize>
+}
+
+impl<'a> Display for ArrangementView<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for track in self.tracks.iter() {
+            writeln!(f, "TRACK {}", track + 1)?;
+            writeln!(f, "RW CH:S PH:S N   V  I")?;
+
+            for arranged in track_steps(self.song, *track, self.rows.clone()) {
+                let step = arranged.step;
+                write!(f, "{:02X} {:02X}:{:X} {:02X}:{:X} {} ",
+                    arranged.row,
+                    arranged.chain,
+                    arranged.chain_step,
+                    arranged.phrase,
+                    arranged.phrase_step,
+                    arranged.note())?;
+
+                if step.velocity == EMPTY {
+                    write!(f, "-- ")?
+                } else {
+                    write!(f, "{:02X} ", step.velocity)?
+                }
+
+                match self.song.instruments.get(step.instrument as usize) {
+                    None => writeln!(f, "--")?,
+                    Some(instr) =>
+                        writeln!(f, "{:02X} {}", step.instrument, instr.name().unwrap_or(""))?
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,10 +1,10 @@
-use std::{collections::hash_map::Entry, path::PathBuf};
+use std::{collections::hash_map::Entry, path::PathBuf, sync::Arc};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use m8_file_parser::{reader::*, Instrument};
 
-use crate::types::M8FstoErr;
+use crate::{ignore_rules::IgnoreRules, types::{M8FstoErr, Outcome}};
 
 pub(crate) fn is_sample_absolute(sample_path: &str) -> bool {
     let ch = sample_path.chars().nth(0).unwrap();
@@ -26,16 +26,19 @@ pub(crate) fn sample_to_absolute_path(
     }
 }
 
-fn on_file_blob(cwd: &Path, path: &Path, data: Vec<u8>) -> Result<HashMap<String, Vec<usize>>, M8FstoErr> {
+fn on_file_blob(cwd: &Path, ignore: &IgnoreRules, path: &Path, data: Vec<u8>) -> Result<HashMap<String, Vec<usize>>, M8FstoErr> {
     let mut reader = Reader::new(data);
     let song = m8_file_parser::Song::
         read_from_reader(&mut reader)
         .map_err(|e| M8FstoErr::UnparseableM8File {
             path: path.to_path_buf(),
-            reason: format!("{:?}", e)
+            source: Arc::new(e)
         })?;
 
     let mut missings = HashMap::new();
+    if ignore.is_version_ignored(&song.version) {
+        return Ok(missings)
+    }
 
     for (i, instr) in song.instruments.iter().enumerate() {
         match instr {
@@ -62,7 +65,7 @@ fn on_file_blob(cwd: &Path, path: &Path, data: Vec<u8>) -> Result<HashMap<String
 }
 
 /// Recursively search a directory for song files and report broken samples
-pub fn find_broken_samples_under_dir(cwd: &Path) -> Result<(), M8FstoErr>{
+pub fn find_broken_samples_under_dir(cwd: &Path, ignore: &IgnoreRules) -> Result<Outcome, M8FstoErr>{
     let pattern = cwd.join("**").join("*.m8s")
         .as_os_str()
         .to_str()
@@ -75,38 +78,34 @@ pub fn find_broken_samples_under_dir(cwd: &Path) -> Result<(), M8FstoErr>{
             M8FstoErr::InvalidSearchPattern { pattern: format!("{:?}", e) })?;
 
     let mut errors = vec![];
+    let mut outcome = Outcome::Clean;
     let cwd = cwd.to_path_buf();
     for entry in files {
         match entry {
             Err(_) => {}
+            Ok(path) if ignore.is_ignored(&path) => {}
             Ok(path) => {
-                if let Err(e) = find_broken_sample_in_song(&cwd, path) {
-                    errors.push(e);
+                match find_broken_sample_in_song(&cwd, ignore, path) {
+                    Ok(o) => outcome = outcome.merge(o),
+                    Err(e) => errors.push(e)
                 }
-
             }
         }
     }
 
-    if errors.is_empty() {
-        Ok(())
-    } else if errors.len() == 1 {
-        Err(errors[0].clone())
-    } else {
-        Err(M8FstoErr::MultiErrs { inner: errors })
-    }
+    M8FstoErr::from_file_errors(errors).map(|()| outcome)
 }
 
 
 /// Report broken samples in a single `.m8s` song file.
-pub fn find_broken_sample_in_song(backup_root : &PathBuf, song_path: PathBuf) -> Result<(), M8FstoErr> {
+pub fn find_broken_sample_in_song(backup_root : &PathBuf, ignore: &IgnoreRules, song_path: PathBuf) -> Result<Outcome, M8FstoErr> {
     let file_blob = fs::read(&song_path).map_err(|e| M8FstoErr::CannotReadFile {
         path: song_path.clone(),
-        reason: format!("{:?}", e),
+        source: Arc::new(e),
     })?;
 
-    match on_file_blob(&backup_root, &song_path, file_blob) {
-        Ok(result) if result.is_empty() => Ok(()),
+    match on_file_blob(&backup_root, ignore, &song_path, file_blob) {
+        Ok(result) if result.is_empty() => Ok(Outcome::Clean),
         Ok(result) => {
             println!("== Broken song {:?}", &song_path);
             for (sample_path, instrs) in result.iter() {
@@ -116,7 +115,7 @@ pub fn find_broken_sample_in_song(backup_root : &PathBuf, song_path: PathBuf) ->
                 }
                 println!("]")
             }
-            Ok(())
+            Ok(Outcome::Findings)
         }
         Err(e) => Err(e),
     }
@@ -124,7 +123,7 @@ pub fn find_broken_sample_in_song(backup_root : &PathBuf, song_path: PathBuf) ->
 
 
 /// Report broken song samples in a list of directories and/or song paths
-pub fn process_paths(cwd: &Path, paths: &[String]) -> Result<(), M8FstoErr> {
+pub fn process_paths(cwd: &Path, ignore: &IgnoreRules, paths: &[String]) -> Result<Outcome, M8FstoErr> {
     let mut roots = Vec::new();
     let mut songs = Vec::new();
 
@@ -133,6 +132,7 @@ pub fn process_paths(cwd: &Path, paths: &[String]) -> Result<(), M8FstoErr> {
     }
 
     let mut errors = vec![];
+    let mut outcome = Outcome::Clean;
     for path in paths {
         let path_buf = PathBuf::from(path);
         if path_buf.is_dir() {
@@ -146,23 +146,19 @@ pub fn process_paths(cwd: &Path, paths: &[String]) -> Result<(), M8FstoErr> {
     }
 
     for root in roots {
-        if let Err(e) = find_broken_samples_under_dir(root.as_path()) {
-            errors.push(e);
+        match find_broken_samples_under_dir(root.as_path(), ignore) {
+            Ok(o) => outcome = outcome.merge(o),
+            Err(e) => errors.push(e)
         }
     }
 
     let cwd = &cwd.to_path_buf();
     for song in songs {
-        if let Err(e) = find_broken_sample_in_song(cwd, song) {
-            errors.push(e);
+        match find_broken_sample_in_song(cwd, ignore, song) {
+            Ok(o) => outcome = outcome.merge(o),
+            Err(e) => errors.push(e)
         }
     }
 
-    if errors.is_empty() {
-        Ok(())
-    } else if errors.len() == 1 {
-        Err(errors[0].clone())
-    } else {
-        Err(M8FstoErr::MultiErrs { inner: errors })
-    }
+    M8FstoErr::from_file_errors(errors).map(|()| outcome)
 }
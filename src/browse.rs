@@ -0,0 +1,410 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::{Instrument, Song};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, ListState, Paragraph},
+    DefaultTerminal, Frame
+};
+
+use crate::{
+    arrangement::{self, EMPTY, SONG_ROW_COUNT, TRACK_COUNT},
+    colorize::{self, line_roles, view_rows, Rgb, Role, Theme},
+    ignore_rules::IgnoreRules,
+    show_song::ElemDisplay,
+    song_file::read_song_file,
+    song_scan::songs_under_dir,
+    types::M8FstoErr
+};
+
+/// Number of steps in chains, phrases and tables
+const STEP_COUNT : usize = 16;
+
+/// A screen of the browser, the screens are stacked while drilling
+/// down from the backup to a table.
+enum Screen {
+    Backup,
+    Song { row: usize, track: usize },
+    Chain { id: usize, row: usize },
+    Phrase { id: usize, row: usize },
+    Instrument { id: usize, scroll: usize },
+    Table { id: usize, row: usize }
+}
+
+struct Browser {
+    root: PathBuf,
+
+    /// Songs of the backup, empty when browsing a single song
+    songs: Vec<PathBuf>,
+
+    /// Selected song in the backup listing
+    selected: usize,
+
+    /// Sample listing of the backup songs, filled when selected
+    samples: HashMap<usize, Vec<String>>,
+
+    /// Opened song
+    song: Option<Song>,
+
+    screens: Vec<Screen>,
+    theme: Theme,
+
+    /// Last error, displayed in the help line
+    message: String
+}
+
+fn rgb(c: Rgb) -> Color {
+    Color::Rgb(c.0, c.1, c.2)
+}
+
+fn terminal_error(e: std::io::Error) -> M8FstoErr {
+    M8FstoErr::TerminalError { source: Arc::new(e) }
+}
+
+/// Samplers of a song, as printed by `ls-sample`
+fn sample_lines(song: &Song) -> Vec<String> {
+    let lines : Vec<String> = song.instruments
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instr)| match instr {
+            Instrument::Sampler(s) if s.name.is_empty() => Some(format!("{:02X} : {}", i, s.sample_path)),
+            Instrument::Sampler(s) => Some(format!("{:02X} {} : {}", i, s.name, s.sample_path)),
+            _ => None
+        })
+        .collect();
+
+    if lines.is_empty() { vec!["No sampler".into()] } else { lines }
+}
+
+/// Instrument view as printed by `show instrument`
+fn instrument_text(song: &Song, id: usize) -> String {
+    ElemDisplay { instr: song.instruments[id].clone(), ver: song.version }.to_string()
+}
+
+/// Color a rendered view like `show --color` does, highlighting the
+/// cursor row, or only one of its cells when `column` is set. Also
+/// return the line of the cursor.
+fn styled_view(text: &str, columns: &[Role], theme: &Theme, row: usize, column: Option<usize>) -> (Vec<Line<'static>>, usize) {
+    let lines : Vec<&str> = text.lines().collect();
+    let rows = view_rows(text);
+    let cursor_line = rows.iter().position(|r| *r == Some(row));
+
+    let styled = lines.iter().enumerate().map(|(i, line)| {
+        let mut cell = 0;
+        let spans : Vec<Span<'static>> = line_roles(line, columns, rows[i].is_some())
+            .into_iter()
+            .map(|(segment, role)| match role {
+                None => Span::raw(segment.to_string()),
+                Some(role) => {
+                    let mut style = Style::default().fg(rgb(role.color(theme)));
+                    if role == Role::Empty {
+                        style = style.add_modifier(Modifier::DIM);
+                    }
+                    if Some(i) == cursor_line && column.is_none_or(|c| c == cell) {
+                        style = style.add_modifier(Modifier::REVERSED);
+                    }
+                    cell += 1;
+                    Span::styled(segment.to_string(), style)
+                }
+            })
+            .collect();
+
+        Line::from(spans)
+    }).collect();
+
+    (styled, cursor_line.unwrap_or(0))
+}
+
+impl Browser {
+    fn screen(&self) -> &Screen {
+        self.screens.last().unwrap_or(&Screen::Backup)
+    }
+
+    fn screen_name(&self, screen: &Screen) -> String {
+        match screen {
+            Screen::Backup => format!("BACKUP {}", self.root.display()),
+            Screen::Song { .. } => format!("SONG {}", self.song.as_ref().map_or("", |s| s.name.as_str())),
+            Screen::Chain { id, .. } => format!("CHAIN {:02X}", id),
+            Screen::Phrase { id, .. } => format!("PHRASE {:02X}", id),
+            Screen::Instrument { id, .. } => format!("INSTRUMENT {:02X}", id),
+            Screen::Table { id, .. } => format!("TABLE {:02X}", id)
+        }
+    }
+
+    /// Load the sample list of the selected backup song
+    fn prepare(&mut self) {
+        if !matches!(self.screen(), Screen::Backup) || self.samples.contains_key(&self.selected) {
+            return
+        }
+
+        let lines = match read_song_file(&self.songs[self.selected]) {
+            Ok((song, _)) => sample_lines(&song),
+            Err(e) => vec![e.to_string().trim().to_string()]
+        };
+
+        self.samples.insert(self.selected, lines);
+    }
+
+    /// Move the cursor up or down
+    fn step(&mut self, delta: isize) {
+        let song_count = self.songs.len();
+        let instrument_lines = match (self.screen(), &self.song) {
+            (Screen::Instrument { id, .. }, Some(song)) => instrument_text(song, *id).lines().count(),
+            _ => 0
+        };
+        let selected = &mut self.selected;
+        let (cursor, count) = match self.screens.last_mut() {
+            None | Some(Screen::Backup) => (selected, song_count),
+            Some(Screen::Song { row, .. }) => (row, SONG_ROW_COUNT),
+            Some(Screen::Chain { row, .. })
+            | Some(Screen::Phrase { row, .. })
+            | Some(Screen::Table { row, .. }) => (row, STEP_COUNT),
+            Some(Screen::Instrument { scroll, .. }) => (scroll, instrument_lines)
+        };
+
+        *cursor = cursor.saturating_add_signed(delta).min(count.saturating_sub(1));
+    }
+
+    /// Move between the tracks of the song grid
+    fn change_track(&mut self, delta: isize) {
+        if let Some(Screen::Song { track, .. }) = self.screens.last_mut() {
+            *track = track.saturating_add_signed(delta).min(TRACK_COUNT - 1);
+        }
+    }
+
+    /// Open the element under the cursor
+    fn open(&mut self) {
+        let next = match (self.screen(), &self.song) {
+            (Screen::Backup, _) => match read_song_file(&self.songs[self.selected]) {
+                Ok((song, _)) => {
+                    self.song = Some(song);
+                    Some(Screen::Song { row: 0, track: 0 })
+                }
+                Err(e) => {
+                    self.message = e.to_string().trim().to_string();
+                    None
+                }
+            },
+            (Screen::Song { row, track }, Some(song)) =>
+                arrangement::chain_at(song, *row, *track).map(|id| Screen::Chain { id, row: 0 }),
+            (Screen::Chain { id, row }, Some(song)) => {
+                let phrase = song.chains[*id].steps[*row].phrase;
+                (phrase != EMPTY).then_some(Screen::Phrase { id: phrase as usize, row: 0 })
+            }
+            (Screen::Phrase { id, row }, Some(song)) => {
+                let instrument = song.phrases[*id].steps[*row].instrument as usize;
+                let filled = song.instruments
+                    .get(instrument)
+                    .is_some_and(|instr| !matches!(instr, Instrument::None));
+                filled.then_some(Screen::Instrument { id: instrument, scroll: 0 })
+            }
+            (Screen::Instrument { id, .. }, _) => Some(Screen::Table { id: *id, row: 0 }),
+            _ => None
+        };
+
+        if let Some(screen) = next {
+            self.screens.push(screen);
+        }
+    }
+
+    /// Open the table of the instrument under the cursor
+    fn open_table(&mut self) {
+        let table = match (self.screen(), &self.song) {
+            (Screen::Phrase { id, row }, Some(song)) => {
+                let instrument = song.phrases[*id].steps[*row].instrument as usize;
+                (instrument < song.tables.len()).then_some(instrument)
+            }
+            (Screen::Instrument { id, .. }, _) => Some(*id),
+            _ => None
+        };
+
+        if let Some(id) = table {
+            self.screens.push(Screen::Table { id, row: 0 });
+        }
+    }
+
+    fn back(&mut self) {
+        if self.screens.len() > 1 {
+            self.screens.pop();
+        }
+    }
+
+    /// Handle a key press, return false to quit
+    fn on_key(&mut self, key: KeyEvent) -> bool {
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        self.message.clear();
+
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Up => self.step(-1),
+            KeyCode::Down => self.step(1),
+            KeyCode::PageUp => self.step(-(STEP_COUNT as isize)),
+            KeyCode::PageDown => self.step(STEP_COUNT as isize),
+            KeyCode::Right if shift => self.open(),
+            KeyCode::Left if shift => self.back(),
+            KeyCode::Right => self.change_track(1),
+            KeyCode::Left => self.change_track(-1),
+            KeyCode::Enter => self.open(),
+            KeyCode::Esc | KeyCode::Backspace => self.back(),
+            KeyCode::Char('t') => self.open_table(),
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Lines of the current song screen and the first line to display
+    fn screen_view(&self, song: &Song, height: usize) -> (Vec<Line<'static>>, usize) {
+        let theme = &self.theme;
+        let (lines, cursor) = match self.screen() {
+            Screen::Backup => (vec![], 0),
+            Screen::Song { row, track } =>
+                styled_view(&song.song.to_string(), colorize::SONG_COLUMNS, theme, *row, Some(track + 1)),
+            Screen::Chain { id, row } =>
+                styled_view(&song.chains[*id].to_string(), colorize::CHAIN_COLUMNS, theme, *row, None),
+            Screen::Phrase { id, row } =>
+                styled_view(&song.phrase_view(*id).to_string(), colorize::PHRASE_COLUMNS, theme, *row, None),
+            Screen::Table { id, row } =>
+                styled_view(&song.table_view(*id).to_string(), colorize::TABLE_COLUMNS, theme, *row, None),
+            Screen::Instrument { id, scroll } => {
+                let lines : Vec<Line<'static>> = instrument_text(song, *id)
+                    .lines()
+                    .map(|l| Line::styled(l.to_string(), Style::default().fg(rgb(theme.text_default))))
+                    .collect();
+                let top = (*scroll).min(lines.len().saturating_sub(1));
+                return (lines, top)
+            }
+        };
+
+        (lines, (cursor + 1).saturating_sub(height))
+    }
+
+    fn draw_backup(&self, frame: &mut Frame, area: Rect) {
+        let [list_area, samples_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(area);
+
+        let names : Vec<String> = self.songs
+            .iter()
+            .map(|p| p.strip_prefix(&self.root).unwrap_or(p).display().to_string())
+            .collect();
+
+        let list = List::new(names)
+            .block(Block::bordered().title("Songs"))
+            .style(Style::default().fg(rgb(self.theme.text_default)))
+            .highlight_style(Style::default().fg(rgb(self.theme.text_value)).add_modifier(Modifier::REVERSED));
+
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, list_area, &mut state);
+
+        let samples : Vec<Line<'static>> = self.samples
+            .get(&self.selected)
+            .map_or(&[][..], |s| s.as_slice())
+            .iter()
+            .map(|l| Line::styled(l.clone(), Style::default().fg(rgb(self.theme.text_default))))
+            .collect();
+
+        frame.render_widget(
+            Paragraph::new(samples).block(Block::bordered().title("Samples")),
+            samples_area);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [title_area, body, help_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+        let title = self.screens
+            .iter()
+            .map(|s| self.screen_name(s))
+            .collect::<Vec<_>>()
+            .join(" > ");
+
+        frame.render_widget(
+            Paragraph::new(title).style(Style::default().fg(rgb(self.theme.text_title))),
+            title_area);
+
+        match (self.screen(), &self.song) {
+            (Screen::Backup, _) | (_, None) => self.draw_backup(frame, body),
+            (_, Some(song)) => {
+                let height = body.height.saturating_sub(2) as usize;
+                let (lines, top) = self.screen_view(song, height);
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .block(Block::bordered())
+                        .scroll((top as u16, 0)),
+                    body);
+            }
+        }
+
+        let help =
+            if !self.message.is_empty() { self.message.clone() }
+            else { "arrows: move  enter/shift+right: open  esc/shift+left: back  t: table  q: quit".to_string() };
+
+        frame.render_widget(
+            Paragraph::new(help).style(Style::default().fg(rgb(self.theme.text_info))),
+            help_area);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), M8FstoErr> {
+        loop {
+            self.prepare();
+            terminal.draw(|frame| self.draw(frame)).map_err(terminal_error)?;
+
+            if let Event::Key(key) = event::read().map_err(terminal_error)? {
+                if key.kind == KeyEventKind::Press && !self.on_key(key) {
+                    return Ok(())
+                }
+            }
+        }
+    }
+}
+
+/// Browse a song, or all the songs of a backup folder, in a terminal
+/// interface navigating like the M8: song, chain, phrase, instrument
+/// and table screens.
+pub fn browse(path: &Path, theme: Option<&str>) -> Result<(), M8FstoErr> {
+    let theme = match theme {
+        None => Theme::default(),
+        Some(p) => Theme::read(Path::new(p))?
+    };
+
+    let mut browser =
+        if path.is_dir() {
+            let ignore = IgnoreRules::load(path, &[])?;
+            let songs = songs_under_dir(path, &ignore)?;
+            if songs.is_empty() {
+                return Err(M8FstoErr::InvalidPath { reason: format!("No song found in {:?}", path) })
+            }
+
+            Browser {
+                root: path.to_path_buf(),
+                songs,
+                selected: 0,
+                samples: HashMap::new(),
+                song: None,
+                screens: vec![Screen::Backup],
+                theme,
+                message: String::new()
+            }
+        } else {
+            let (song, _) = read_song_file(path)?;
+            Browser {
+                root: path.parent().map_or_else(PathBuf::new, Path::to_path_buf),
+                songs: vec![],
+                selected: 0,
+                samples: HashMap::new(),
+                song: Some(song),
+                screens: vec![Screen::Song { row: 0, track: 0 }],
+                theme,
+                message: String::new()
+            }
+        };
+
+    let mut terminal = ratatui::try_init().map_err(terminal_error)?;
+    let result = browser.run(&mut terminal);
+    ratatui::restore();
+    result
+}
@@ -1,4 +1,4 @@
-use std::{collections::{hash_map::Entry, HashMap}, fs, path::{Path, PathBuf}};
+use std::{collections::{hash_map::Entry, HashMap}, fs, path::{Path, PathBuf}, sync::Arc};
 use m8_file_parser::{reader::*, writer::Writer, Instrument};
 
 use crate::{broken_search::sample_to_absolute_path, types::M8FstoErr};
@@ -8,7 +8,7 @@ fn on_file_blob(backup_root: &Path, song_path: &Path, out_folder: &Path, data: V
     let mut song = m8_file_parser::Song::read_from_reader(&mut reader)
         .map_err(|e| M8FstoErr::UnparseableM8File {
             path: song_path.to_path_buf(),
-            reason: format!("{:?}", e)
+            source: Arc::new(e)
         })?;
 
     // First pass we verify that all the samples exists, before effectively
@@ -31,14 +31,14 @@ fn on_file_blob(backup_root: &Path, song_path: &Path, out_folder: &Path, data: V
     std::fs::create_dir(&out_folder).map_err(|e|
         M8FstoErr::FolderCreationError {
             path: out_folder.clone(),
-            reason: format!("{:?}", e)
+            source: Arc::new(e)
         })?;
 
     let sample_folder_path = out_folder.join("Samples");
     std::fs::create_dir(&sample_folder_path).map_err(|e|
         M8FstoErr::FolderCreationError {
             path: out_folder.clone(),
-            reason: format!("{:?}", e)
+            source: Arc::new(e)
         })?;
 
     let mut samples : HashMap<String, String> = HashMap::new();
@@ -68,7 +68,7 @@ fn on_file_blob(backup_root: &Path, song_path: &Path, out_folder: &Path, data: V
                             .map_err(|e| M8FstoErr::SampleCopyError {
                                 path: full_sample_path.clone(),
                                 to: out_sample_path,
-                                reason: format!("{:?}", e) })?;
+                                source: Arc::new(e) })?;
 
                         let relative_name = format!("Samples/{}", out_filename);
                         sampler.sample_path = relative_name.clone();
@@ -93,10 +93,10 @@ fn on_file_blob(backup_root: &Path, song_path: &Path, out_folder: &Path, data: V
             })?;
 
     std::fs::write(&out_song_name, writer.finish())
-        .map_err(|reason|
-            M8FstoErr::SongSerializationError {
-                destination: format!("{:?}", out_song_name),
-                reason: format!("{:?}", reason)
+        .map_err(|e|
+            M8FstoErr::FileWriteError {
+                path: out_song_name.clone(),
+                source: Arc::new(e)
             })?;
     
     Ok(())
@@ -106,7 +106,7 @@ fn on_file_blob(backup_root: &Path, song_path: &Path, out_folder: &Path, data: V
 pub fn bundle_song(cwd: &Path, path : &str, out_folder: &Option<String>) -> Result<(), M8FstoErr> {
     let file_blob = fs::read(path)
         .map_err(|e|
-            M8FstoErr::CannotReadFile { path: PathBuf::from(path), reason: format!("{:?}", e) })?;
+            M8FstoErr::CannotReadFile { path: PathBuf::from(path), source: Arc::new(e) })?;
 
     let as_path = Path::new(path);
 
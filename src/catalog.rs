@@ -0,0 +1,179 @@
+use std::{fs, path::{Path, PathBuf}, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use clap::ValueEnum;
+use m8_file_parser::{Instrument, Song};
+
+use crate::{broken_search::sample_to_absolute_path, ignore_rules::IgnoreRules, show_song::SongStats, song_scan::scan_songs, types::{M8FstoErr, Outcome}};
+
+/// Output format of the catalog
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CatalogFormat {
+    /// Aligned text table
+    Text,
+
+    /// Comma separated values
+    Csv,
+
+    /// Static HTML page
+    Html
+}
+
+const COLUMNS : [&str; 10] = [
+    "Path", "Name", "Version", "Tempo", "Instruments", "Samples",
+    "Chains", "Phrases", "Modified", "Broken samples"
+];
+
+/// Format a file time as `YYYY-MM-DD HH:MM` UTC
+fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (days, day_secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // civil date from the number of days since 1970-01-01
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, day_secs / 3600, (day_secs % 3600) / 60)
+}
+
+/// Samplers whose sample cannot be found
+fn broken_samples(root: &Path, song_path: &Path, song: &Song) -> usize {
+    song.instruments
+        .iter()
+        .filter(|i| match i {
+            Instrument::Sampler(s) if !s.sample_path.is_empty() =>
+                !sample_to_absolute_path(root, song_path, &s.sample_path).exists(),
+            _ => false
+        })
+        .count()
+}
+
+fn catalog_row(root: &Path, song_path: &Path, song: &Song) -> Vec<String> {
+    let stats = SongStats::of(song);
+    let modified = fs::metadata(song_path)
+        .and_then(|m| m.modified())
+        .map_or_else(|_| String::new(), format_time);
+
+    vec![
+        song_path.strip_prefix(root).unwrap_or(song_path).display().to_string(),
+        song.name.clone(),
+        song.version.to_string(),
+        format!("{:.2}", song.tempo),
+        stats.instruments.mix(),
+        stats.distinct_samples.to_string(),
+        stats.used_chains.to_string(),
+        stats.used_phrases.to_string(),
+        modified,
+        broken_samples(root, song_path, song).to_string()
+    ]
+}
+
+fn write_text(rows: &[Vec<String>], w: &mut dyn std::io::Write) -> std::io::Result<()> {
+    let widths : Vec<usize> = (0 .. COLUMNS.len())
+        .map(|c| rows.iter().map(|r| r[c].chars().count()).chain([COLUMNS[c].len()]).max().unwrap_or(0))
+        .collect();
+
+    let line = |cells: Vec<&str>| cells
+        .iter()
+        .zip(widths.iter())
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect::<Vec<_>>()
+        .join(" | ");
+
+    writeln!(w, "{}", line(COLUMNS.to_vec()).trim_end())?;
+    writeln!(w, "{}", widths.iter().map(|n| "-".repeat(*n)).collect::<Vec<_>>().join("-+-"))?;
+    for row in rows {
+        writeln!(w, "{}", line(row.iter().map(|c| c.as_str()).collect()).trim_end())?;
+    }
+
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_csv(rows: &[Vec<String>], w: &mut dyn std::io::Write) -> std::io::Result<()> {
+    writeln!(w, "{}", COLUMNS.join(","))?;
+    for row in rows {
+        writeln!(w, "{}", row.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","))?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_html(rows: &[Vec<String>], w: &mut dyn std::io::Write) -> std::io::Result<()> {
+    writeln!(w, "<!DOCTYPE html>")?;
+    writeln!(w, "<html>")?;
+    writeln!(w, "<head>")?;
+    writeln!(w, "<meta charset=\"utf-8\">")?;
+    writeln!(w, "<title>M8 song catalog</title>")?;
+    writeln!(w, "<style>")?;
+    writeln!(w, "body {{ font-family: monospace; background: #111; color: #ddd; }}")?;
+    writeln!(w, "table {{ border-collapse: collapse; }}")?;
+    writeln!(w, "th, td {{ padding: 2px 8px; border-bottom: 1px solid #333; text-align: left; }}")?;
+    writeln!(w, "th {{ color: #6cf; }}")?;
+    writeln!(w, "tr:hover {{ background: #222; }}")?;
+    writeln!(w, "</style>")?;
+    writeln!(w, "</head>")?;
+    writeln!(w, "<body>")?;
+    writeln!(w, "<h1>M8 song catalog</h1>")?;
+    writeln!(w, "<table>")?;
+
+    let header : Vec<String> = COLUMNS.iter().map(|c| format!("<th>{}</th>", c)).collect();
+    writeln!(w, "<tr>{}</tr>", header.join(""))?;
+    for row in rows {
+        let cells : Vec<String> = row.iter().map(|c| format!("<td>{}</td>", html_escape(c))).collect();
+        writeln!(w, "<tr>{}</tr>", cells.join(""))?;
+    }
+
+    writeln!(w, "</table>")?;
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")
+}
+
+/// Describe every song under a root folder, as a text table, CSV or
+/// HTML page, written in `output` or on the standard output.
+pub fn catalog(root: &Path, ignore: &IgnoreRules, format: CatalogFormat, output: Option<&str>) -> Result<(), M8FstoErr> {
+    let mut rows = vec![];
+    let scan = scan_songs(root, ignore, &[], |song_path, song| {
+        rows.push(catalog_row(root, song_path, &song));
+        Ok(Outcome::Clean)
+    });
+
+    rows.sort();
+
+    let mut out : Box<dyn std::io::Write> = match output {
+        None => Box::new(std::io::stdout()),
+        Some(path) => Box::new(fs::File::create(path)
+            .map_err(|e| M8FstoErr::FileWriteError { path: PathBuf::from(path), source: Arc::new(e) })?)
+    };
+
+    let written = match format {
+        CatalogFormat::Text => write_text(&rows, &mut out),
+        CatalogFormat::Csv => write_csv(&rows, &mut out),
+        CatalogFormat::Html => write_html(&rows, &mut out)
+    };
+
+    written.map_err(|_| M8FstoErr::PrintError)?;
+
+    // unreadable songs are reported after the catalog
+    scan.map(|_| ())
+}
@@ -0,0 +1,235 @@
+use std::{fs, io::IsTerminal, path::Path, sync::Arc};
+
+use clap::ValueEnum;
+
+use crate::types::M8FstoErr;
+
+/// When to color the output of `show`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when printing to a terminal, unless `NO_COLOR` is set
+    Auto,
+    Always,
+    Never
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto =>
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+/// Colors of an M8 theme used by the console rendering
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub text_empty: Rgb,
+    pub text_info: Rgb,
+    pub text_default: Rgb,
+    pub text_value: Rgb,
+    pub text_title: Rgb,
+    pub cursor: Rgb,
+    pub meter_low: Rgb,
+    pub meter_mid: Rgb
+}
+
+/// Size of the `M8VERSION` header preceding the colors
+const THEME_HEADER_SIZE : usize = 14;
+
+/// Colors of a `.m8t` file, in order: background, text empty, text info,
+/// text default, text value, text title, play marker, cursor, selection,
+/// scope, meter low, meter mid and meter peak.
+const THEME_COLOR_COUNT : usize = 13;
+
+impl Default for Theme {
+    /// Colors of the device default theme
+    fn default() -> Self {
+        Theme {
+            text_empty: Rgb(0x3C, 0x3C, 0x50),
+            text_info: Rgb(0x60, 0x60, 0x8E),
+            text_default: Rgb(0x8C, 0x8C, 0xBA),
+            text_value: Rgb(0xFA, 0xFA, 0xFA),
+            text_title: Rgb(0x32, 0xEC, 0xFF),
+            cursor: Rgb(0x32, 0xEC, 0xFF),
+            meter_low: Rgb(0x00, 0xFF, 0x00),
+            meter_mid: Rgb(0xFF, 0xFF, 0x00)
+        }
+    }
+}
+
+impl Theme {
+    pub fn read(path: &Path) -> Result<Theme, M8FstoErr> {
+        let blob = fs::read(path)
+            .map_err(|e| M8FstoErr::CannotReadFile { path: path.to_path_buf(), source: Arc::new(e) })?;
+
+        let invalid = |reason: &str| M8FstoErr::InvalidTheme {
+            path: path.to_path_buf(),
+            reason: reason.to_string()
+        };
+
+        if !blob.starts_with(b"M8VERSION") {
+            return Err(invalid("missing M8VERSION header"))
+        }
+
+        let colors : Vec<Rgb> = blob
+            .get(THEME_HEADER_SIZE .. THEME_HEADER_SIZE + THEME_COLOR_COUNT * 3)
+            .ok_or_else(|| invalid("file too short"))?
+            .chunks(3)
+            .map(|c| Rgb(c[0], c[1], c[2]))
+            .collect();
+
+        Ok(Theme {
+            text_empty: colors[1],
+            text_info: colors[2],
+            text_default: colors[3],
+            text_value: colors[4],
+            text_title: colors[5],
+            cursor: colors[7],
+            meter_low: colors[10],
+            meter_mid: colors[11]
+        })
+    }
+}
+
+/// Meaning of a token in a rendered view
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Title,
+    Row,
+    Note,
+    Velocity,
+    Instrument,
+    Fx,
+    Value,
+    Empty
+}
+
+impl Role {
+    pub fn color(self, theme: &Theme) -> Rgb {
+        match self {
+            Role::Title => theme.text_title,
+            Role::Row => theme.text_info,
+            Role::Note => theme.text_value,
+            Role::Velocity => theme.meter_low,
+            Role::Instrument => theme.meter_mid,
+            Role::Fx => theme.cursor,
+            Role::Value => theme.text_default,
+            Role::Empty => theme.text_empty
+        }
+    }
+}
+
+/// Columns of the rendered views, after the row number
+pub const PHRASE_COLUMNS : &[Role] = &[Role::Note, Role::Velocity, Role::Instrument, Role::Fx];
+pub const TABLE_COLUMNS : &[Role] = &[Role::Note, Role::Velocity, Role::Fx];
+pub const CHAIN_COLUMNS : &[Role] = &[Role::Value];
+pub const SONG_COLUMNS : &[Role] = &[Role::Value];
+
+/// Row number of each line of a rendered view, `None` for the headers.
+/// The views print their headers above the first row, numbered 0, and
+/// the row numbers then only go up, so a header line can't be taken for
+/// a row even when it starts with a number, like the track numbers of
+/// the song view.
+pub fn view_rows(text: &str) -> Vec<Option<usize>> {
+    let mut last : Option<usize> = None;
+    text.lines()
+        .map(|line| {
+            let number = line
+                .split_whitespace()
+                .next()
+                .and_then(|t| usize::from_str_radix(t, 16).ok());
+
+            let is_row = match (last, number) {
+                (None, Some(n)) => n == 0,
+                (Some(l), Some(n)) => n > l,
+                (_, None) => false
+            };
+
+            if is_row {
+                last = number;
+                number
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Split a view line in tokens, keeping the whitespace between them,
+/// and give a role to each token. Header lines get the title role, the
+/// last column role is used for all the remaining tokens of a row.
+pub fn line_roles<'a>(line: &'a str, columns: &[Role], is_row: bool) -> Vec<(&'a str, Option<Role>)> {
+    let mut segments = vec![];
+    let mut column = 0;
+    let mut rest = line;
+    while !rest.is_empty() {
+        let space = rest.starts_with(char::is_whitespace);
+        let end = rest
+            .find(|c: char| c.is_whitespace() != space)
+            .unwrap_or(rest.len());
+        let (segment, tail) = rest.split_at(end);
+        rest = tail;
+
+        if space {
+            segments.push((segment, None));
+            continue;
+        }
+
+        let role =
+            if !is_row { Role::Title }
+            else if segment.chars().all(|c| c == '-') { Role::Empty }
+            else if column == 0 { Role::Row }
+            else { columns[(column - 1).min(columns.len() - 1)] };
+
+        segments.push((segment, Some(role)));
+        column += 1;
+    }
+
+    segments
+}
+
+/// Add ANSI colors to a rendered view, empty cells being dimmed
+pub fn paint(text: &str, columns: &[Role], theme: &Theme) -> String {
+    let mut out = String::with_capacity(text.len() * 4);
+    let rows = view_rows(text);
+    for (line, row) in text.split_inclusive('\n').zip(rows) {
+        let (line, eol) = match line.strip_suffix('\n') {
+            Some(l) => (l, "\n"),
+            None => (line, "")
+        };
+
+        for (segment, role) in line_roles(line, columns, row.is_some()) {
+            match role {
+                None => out.push_str(segment),
+                Some(role) => {
+                    let Rgb(r, g, b) = role.color(theme);
+                    let dim = if role == Role::Empty { "\x1b[2m" } else { "" };
+                    out.push_str(&format!("{}\x1b[38;2;{};{};{}m{}\x1b[0m", dim, r, g, b, segment));
+                }
+            }
+        }
+        out.push_str(eol);
+    }
+
+    out
+}
+
+/// Theme to use for the output, `None` when it must not be colored
+pub fn output_theme(choice: ColorChoice, theme_path: Option<&str>) -> Result<Option<Theme>, M8FstoErr> {
+    if !choice.enabled() {
+        return Ok(None)
+    }
+
+    match theme_path {
+        None => Ok(Some(Theme::default())),
+        Some(p) => Theme::read(Path::new(p)).map(Some)
+    }
+}
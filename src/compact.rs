@@ -0,0 +1,316 @@
+use std::{fs, path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::{writer::Writer, Chain, Instrument, Note, Phrase, Song, Table, FX};
+
+use crate::{arrangement::{EMPTY, SONG_ROW_COUNT, TRACK_COUNT}, fx_names::{phrase_fx, table_fx, TABLE_FX}, song_file::read_song_file, types::{FlagBag, M8FstoErr, Outcome}};
+
+/// FX slot of an empty step
+const EMPTY_FX : FX = FX { command: EMPTY, value: 0 };
+
+/// What to do with the elements which are not reachable
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CompactMode {
+    /// Only list them
+    List,
+
+    /// Clear them
+    Clear,
+
+    /// Clear them and move the used elements to the lowest slots
+    Renumber
+}
+
+/// Elements reachable from the song grid
+pub struct Reachability {
+    pub chains: Vec<bool>,
+    pub phrases: Vec<bool>,
+    pub instruments: Vec<bool>,
+    pub tables: Vec<bool>
+}
+
+fn mark(flags: &mut [bool], id: u8) -> bool {
+    match flags.get_mut(id as usize) {
+        Some(f) if id != EMPTY && !*f => {
+            *f = true;
+            true
+        }
+        _ => false
+    }
+}
+
+/// Follow the song grid through chains to phrases, and from the phrases
+/// to their instruments and tables. Each instrument uses the table with
+/// the same number, and `TBX` effects play other tables. The instruments
+/// played by the MIDI track inputs are reachable too.
+pub fn reachability(song: &Song) -> Reachability {
+    let mut chains = vec![false; song.chains.len()];
+    for cell in song.song.steps.iter().take(SONG_ROW_COUNT * TRACK_COUNT) {
+        mark(&mut chains, *cell);
+    }
+
+    let mut r = reachable_from_chains(song, chains);
+
+    let mut pending_tables = vec![];
+    for instrument in song.midi_settings.track_input_intrument.iter() {
+        let exists = song.instruments
+            .get(*instrument as usize)
+            .is_some_and(|i| !matches!(i, Instrument::None));
+
+        if exists && mark(&mut r.instruments, *instrument) && mark(&mut r.tables, *instrument) {
+            pending_tables.push(*instrument as usize);
+        }
+    }
+
+    follow_tables(song, &mut r.tables, pending_tables);
+    r
+}
+
+/// Elements reachable from a set of chains, following them like
+/// `reachability` does from the song grid.
+pub fn reachable_from_chains(song: &Song, chains: Vec<bool>) -> Reachability {
+    let mut r = Reachability {
+        chains,
+        phrases: vec![false; song.phrases.len()],
+        instruments: vec![false; song.instruments.len()],
+        tables: vec![false; song.tables.len()]
+    };
+
+    for chain in (0 .. song.chains.len()).filter(|c| r.chains[*c]) {
+        for step in song.chains[chain].steps.iter() {
+            mark(&mut r.phrases, step.phrase);
+        }
+    }
+
+    let mut pending_tables = vec![];
+    for phrase in (0 .. song.phrases.len()).filter(|p| r.phrases[*p]) {
+        for step in song.phrases[phrase].steps.iter() {
+            if mark(&mut r.instruments, step.instrument) && mark(&mut r.tables, step.instrument) {
+                pending_tables.push(step.instrument as usize);
+            }
+        }
+
+        for fx in phrase_fx(song, phrase).iter().filter(|fx| fx.name == TABLE_FX) {
+            if mark(&mut r.tables, fx.value) {
+                pending_tables.push(fx.value as usize);
+            }
+        }
+    }
+
+    follow_tables(song, &mut r.tables, pending_tables);
+    r
+}
+
+/// Mark the tables played by `TBX` effects from the pending tables,
+/// and from the tables they play.
+fn follow_tables(song: &Song, tables: &mut [bool], mut pending_tables: Vec<usize>) {
+    while let Some(table) = pending_tables.pop() {
+        for fx in table_fx(song, table).iter().filter(|fx| fx.name == TABLE_FX) {
+            if mark(tables, fx.value) {
+                pending_tables.push(fx.value as usize);
+            }
+        }
+    }
+}
+
+fn clear_chain(chain: &mut Chain) {
+    for step in chain.steps.iter_mut() {
+        step.phrase = EMPTY;
+        step.transpose = 0;
+    }
+}
+
+fn clear_phrase(phrase: &mut Phrase) {
+    for step in phrase.steps.iter_mut() {
+        step.note = Note(EMPTY);
+        step.velocity = EMPTY;
+        step.instrument = EMPTY;
+        step.fx1 = EMPTY_FX;
+        step.fx2 = EMPTY_FX;
+        step.fx3 = EMPTY_FX;
+    }
+}
+
+fn clear_table(table: &mut Table) {
+    for step in table.steps.iter_mut() {
+        step.transpose = 0;
+        step.velocity = EMPTY;
+        step.fx1 = EMPTY_FX;
+        step.fx2 = EMPTY_FX;
+        step.fx3 = EMPTY_FX;
+    }
+}
+
+fn clear_instrument(instrument: &mut Instrument) {
+    *instrument = Instrument::None;
+}
+
+/// Elements with content which are not reachable
+fn unused<T>(elems: &[T], reachable: &[bool], is_empty: impl Fn(&T) -> bool) -> Vec<usize> {
+    elems
+        .iter()
+        .enumerate()
+        .filter(|(i, e)| !reachable[*i] && !is_empty(e))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// New number of each element, used elements being packed at the start
+fn dense_mapping(used: &[bool]) -> Vec<Option<u8>> {
+    let mut next = 0;
+    used.iter().map(|u| {
+        if !*u { return None }
+        next += 1;
+        Some(next - 1)
+    }).collect()
+}
+
+fn remap(mapping: &[Option<u8>], id: u8) -> u8 {
+    mapping.get(id as usize).copied().flatten().unwrap_or(id)
+}
+
+/// Move the elements to their new number, clearing the others
+fn relocate<T: Clone>(elems: &mut [T], mapping: &[Option<u8>], clear: impl Fn(&mut T)) {
+    let original = elems.to_vec();
+    elems.iter_mut().for_each(&clear);
+
+    for (old, new) in mapping.iter().enumerate() {
+        if let Some(new) = new {
+            elems[*new as usize] = original[old].clone();
+        }
+    }
+}
+
+pub(crate) fn fx_mut<'a>(fx1: &'a mut FX, fx2: &'a mut FX, fx3: &'a mut FX, column: usize) -> &'a mut FX {
+    match column {
+        0 => fx1,
+        1 => fx2,
+        _ => fx3
+    }
+}
+
+fn print_moves(kind: &str, mapping: &[Option<u8>]) {
+    for (old, new) in mapping.iter().enumerate() {
+        match new {
+            Some(new) if *new as usize != old => println!(" * {} {:02X} -> {:02X}", kind, old, new),
+            _ => {}
+        }
+    }
+}
+
+/// Renumber the used elements densely, updating all the references
+fn renumber(song: &mut Song, r: &Reachability) {
+    // instruments and tables are moved together, the instrument
+    // using the table with the same number.
+    let slots : Vec<bool> = r.instruments.iter().zip(r.tables.iter()).map(|(i, t)| *i || *t).collect();
+
+    let chains = dense_mapping(&r.chains);
+    let phrases = dense_mapping(&r.phrases);
+    let instruments = dense_mapping(&slots);
+
+    // The FX names must be resolved before moving anything
+    let phrase_tbx : Vec<_> = (0 .. song.phrases.len())
+        .filter(|p| r.phrases[*p])
+        .flat_map(|p| phrase_fx(song, p).into_iter().filter(|fx| fx.name == TABLE_FX).map(move |fx| (p, fx)))
+        .collect();
+    let table_tbx : Vec<_> = (0 .. song.tables.len())
+        .filter(|t| r.tables[*t])
+        .flat_map(|t| table_fx(song, t).into_iter().filter(|fx| fx.name == TABLE_FX).map(move |fx| (t, fx)))
+        .collect();
+
+    for (p, fx) in phrase_tbx {
+        let step = &mut song.phrases[p].steps[fx.row];
+        let fx_slot = fx_mut(&mut step.fx1, &mut step.fx2, &mut step.fx3, fx.column);
+        fx_slot.value = remap(&instruments, fx_slot.value);
+    }
+
+    for (t, fx) in table_tbx {
+        let step = &mut song.tables[t].steps[fx.row];
+        let fx_slot = fx_mut(&mut step.fx1, &mut step.fx2, &mut step.fx3, fx.column);
+        fx_slot.value = remap(&instruments, fx_slot.value);
+    }
+
+    for cell in song.song.steps.iter_mut().take(SONG_ROW_COUNT * TRACK_COUNT) {
+        *cell = remap(&chains, *cell);
+    }
+
+    for chain in song.chains.iter_mut() {
+        for step in chain.steps.iter_mut() {
+            step.phrase = remap(&phrases, step.phrase);
+        }
+    }
+
+    for phrase in song.phrases.iter_mut() {
+        for step in phrase.steps.iter_mut() {
+            step.instrument = remap(&instruments, step.instrument);
+        }
+    }
+
+    for instrument in song.midi_settings.track_input_intrument.iter_mut() {
+        *instrument = remap(&instruments, *instrument);
+    }
+
+    relocate(&mut song.chains, &chains, clear_chain);
+    relocate(&mut song.phrases, &phrases, clear_phrase);
+    relocate(&mut song.instruments, &instruments, clear_instrument);
+    relocate(&mut song.tables, &instruments, clear_table);
+
+    print_moves("chain", &chains);
+    print_moves("phrase", &phrases);
+    print_moves("instrument", &instruments);
+}
+
+fn print_unused(kind: &str, ids: &[usize]) {
+    if ids.is_empty() {
+        return
+    }
+
+    let hex : Vec<_> = ids.iter().map(|i| format!("{:02X}", i)).collect();
+    println!("Unused {:<12}: {}", kind, hex.join(" "));
+}
+
+/// List the elements which cannot be reached from the song grid,
+/// optionally clearing them or renumbering the used ones.
+pub fn compact_song(flags: FlagBag, song_path: &str, mode: CompactMode) -> Result<Outcome, M8FstoErr> {
+    let (mut song, song_blob) = read_song_file(Path::new(song_path))?;
+    let r = reachability(&song);
+
+    let chains = unused(&song.chains, &r.chains, |c| c.is_empty());
+    let phrases = unused(&song.phrases, &r.phrases, |p| p.is_empty());
+    let instruments = unused(&song.instruments, &r.instruments, |i| matches!(i, Instrument::None));
+    let tables = unused(&song.tables, &r.tables, |t| t.is_empty());
+
+    print_unused("chains", &chains);
+    print_unused("phrases", &phrases);
+    print_unused("instruments", &instruments);
+    print_unused("tables", &tables);
+
+    let found = !(chains.is_empty() && phrases.is_empty() && instruments.is_empty() && tables.is_empty());
+
+    match mode {
+        CompactMode::List => return Ok(Outcome::findings_if(found)),
+        CompactMode::Clear => {
+            chains.iter().for_each(|i| clear_chain(&mut song.chains[*i]));
+            phrases.iter().for_each(|i| clear_phrase(&mut song.phrases[*i]));
+            instruments.iter().for_each(|i| clear_instrument(&mut song.instruments[*i]));
+            tables.iter().for_each(|i| clear_table(&mut song.tables[*i]));
+        }
+        CompactMode::Renumber => renumber(&mut song, &r)
+    }
+
+    if flags.dry_run {
+        return Ok(Outcome::Clean)
+    }
+
+    let mut writer = Writer::new(song_blob);
+    song.write(&mut writer)
+        .map_err(|reason|
+            M8FstoErr::SongSerializationError {
+                destination: song_path.to_string(),
+                reason
+            })?;
+
+    fs::write(song_path, writer.finish())
+        .map_err(|e| M8FstoErr::FileWriteError { path: PathBuf::from(song_path), source: Arc::new(e) })?;
+
+    Ok(Outcome::Clean)
+}
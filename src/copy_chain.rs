@@ -0,0 +1,129 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::writer::Writer;
+
+use crate::{
+    compact::{fx_mut, reachable_from_chains},
+    fx_names::{phrase_fx, table_fx, NamedFx, TABLE_FX},
+    slots::{attach_eq, free_chains, free_instruments, free_phrases, take_slots},
+    song_file::read_song_file,
+    types::{FlagBag, M8FstoErr}
+};
+
+/// Allocate destination slots for the flagged source elements
+fn renumber(used: &[bool], free: &[usize], kind: &'static str) -> Result<BTreeMap<u8, u8>, M8FstoErr> {
+    let sources : Vec<u8> = used
+        .iter()
+        .enumerate()
+        .filter(|(_, u)| **u)
+        .map(|(i, _)| i as u8)
+        .collect();
+
+    let slots = take_slots(free, sources.len(), kind)?;
+    Ok(sources.into_iter().zip(slots.into_iter().map(|s| s as u8)).collect())
+}
+
+/// New value of the `TBX` effects of a phrase or table, pointing
+/// to the copied tables, as `(row, column, table)`
+fn table_moves(fx: Vec<NamedFx>, slots: &BTreeMap<u8, u8>) -> Vec<(usize, usize, u8)> {
+    fx.iter()
+        .filter(|fx| fx.name == TABLE_FX)
+        .filter_map(|fx| slots.get(&fx.value).map(|table| (fx.row, fx.column, *table)))
+        .collect()
+}
+
+fn print_mapping(kind: &str, mapping: &BTreeMap<u8, u8>) {
+    for (src, dst) in mapping.iter() {
+        println!(" * {} {:02X} -> {:02X}", kind, src, dst);
+    }
+}
+
+/// Copy a chain with its phrases, instruments, tables and EQs in free
+/// slots of another song, renumbering the references, including the
+/// tables played by `TBX` effects.
+pub fn copy_chain(flags: FlagBag, src_path: &str, chain: usize, dst_path: &str) -> Result<(), M8FstoErr> {
+    let (src, _) = read_song_file(Path::new(src_path))?;
+    let (mut dst, dst_blob) = read_song_file(Path::new(dst_path))?;
+
+    if src.version != dst.version {
+        return Err(M8FstoErr::IncompatibleSongs {
+            reason: format!("songs use different versions ({}, {})", src.version, dst.version)
+        })
+    }
+
+    let src_chain = &src.chains[chain];
+    let mut chains = vec![false; src.chains.len()];
+    chains[chain] = true;
+    let r = reachable_from_chains(&src, chains);
+
+    // instruments and tables are copied together, the instrument
+    // using the table with the same number.
+    let used_slots : Vec<bool> = r.instruments.iter().zip(r.tables.iter()).map(|(i, t)| *i || *t).collect();
+
+    let phrases = renumber(&r.phrases, &free_phrases(&dst), "phrase")?;
+    let slots = renumber(&used_slots, &free_instruments(&dst), "instrument")?;
+    let dst_chain = take_slots(&free_chains(&dst), 1, "chain")?[0];
+
+    for (src_id, dst_id) in slots.iter() {
+        let (src_id, dst_id) = (*src_id as usize, *dst_id as usize);
+
+        if r.instruments[src_id] {
+            let mut instrument = src.instruments[src_id].clone();
+            let eq = instrument.equ().and_then(|eq| src.eqs.get(eq as usize)).cloned();
+            attach_eq(&mut dst, &mut instrument, eq)?;
+            dst.instruments[dst_id] = instrument;
+        }
+
+        if r.tables[src_id] {
+            let mut table = src.tables[src_id].clone();
+            for (row, column, moved) in table_moves(table_fx(&src, src_id), &slots) {
+                let step = &mut table.steps[row];
+                fx_mut(&mut step.fx1, &mut step.fx2, &mut step.fx3, column).value = moved;
+            }
+            dst.tables[dst_id] = table;
+        }
+    }
+
+    for (src_id, dst_id) in phrases.iter() {
+        let mut phrase = src.phrases[*src_id as usize].clone();
+        for step in phrase.steps.iter_mut() {
+            if let Some(instr) = slots.get(&step.instrument) {
+                step.instrument = *instr;
+            }
+        }
+
+        for (row, column, moved) in table_moves(phrase_fx(&src, *src_id as usize), &slots) {
+            let step = &mut phrase.steps[row];
+            fx_mut(&mut step.fx1, &mut step.fx2, &mut step.fx3, column).value = moved;
+        }
+
+        dst.phrases[*dst_id as usize] = phrase;
+    }
+
+    let mut new_chain = src_chain.clone();
+    for step in new_chain.steps.iter_mut() {
+        if let Some(phrase) = phrases.get(&step.phrase) {
+            step.phrase = *phrase;
+        }
+    }
+    dst.chains[dst_chain] = new_chain;
+
+    println!("Chain {:02X} copied to chain {:02X}", chain, dst_chain);
+    print_mapping("phrase", &phrases);
+    print_mapping("instrument", &slots);
+
+    if flags.dry_run {
+        return Ok(())
+    }
+
+    let mut writer = Writer::new(dst_blob);
+    dst.write(&mut writer)
+        .map_err(|reason|
+            M8FstoErr::SongSerializationError {
+                destination: dst_path.to_string(),
+                reason
+            })?;
+
+    fs::write(dst_path, writer.finish())
+        .map_err(|e| M8FstoErr::FileWriteError { path: PathBuf::from(dst_path), source: Arc::new(e) })
+}
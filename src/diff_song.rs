@@ -0,0 +1,232 @@
+use std::{collections::HashMap, fmt::Display, path::Path};
+
+use clap::ValueEnum;
+use m8_file_parser::{param_gatherer::Describable, Song, Version};
+
+use crate::{arrangement::{self, SONG_ROW_COUNT, TRACK_COUNT}, flat_params::FlatParams, show_song::instrument_kind, song_file::read_song_file, types::{M8FstoErr, Outcome}};
+
+/// How to print the differences between two songs
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    /// Human readable listing, grouped by element
+    Text,
+
+    /// One tab separated line per change: element, id, key,
+    /// value before and value after.
+    Tsv
+}
+
+/// A value changed within an element, an empty value means
+/// it is missing on one side.
+pub struct Change {
+    pub key: String,
+    pub before: String,
+    pub after: String
+}
+
+/// All the changes found in a song element (chain, phrase...)
+pub struct ElementDiff {
+    pub kind: &'static str,
+    pub id: Option<usize>,
+    pub changes: Vec<Change>
+}
+
+impl ElementDiff {
+    fn id_str(&self) -> String {
+        self.id.map_or_else(String::new, |i| format!("{:02X}", i))
+    }
+}
+
+/// Compare two lists of named parameters, the order of the first
+/// list is kept.
+pub fn diff_params(before: &[(String, String)], after: &[(String, String)]) -> Vec<Change> {
+    let after_map : HashMap<&str, &str> = after
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+
+    let before_map : HashMap<&str, &str> = before
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+
+    let mut changes = vec![];
+    for (key, value) in before {
+        match after_map.get(key.as_str()) {
+            Some(v) if v == value => {}
+            other => changes.push(Change {
+                key: key.clone(),
+                before: value.clone(),
+                after: other.unwrap_or(&"").to_string()
+            })
+        }
+    }
+
+    for (key, value) in after {
+        if !before_map.contains_key(key.as_str()) {
+            changes.push(Change {
+                key: key.clone(),
+                before: String::new(),
+                after: value.clone()
+            })
+        }
+    }
+
+    changes
+}
+
+/// Compare the textual views of an element line by line, the
+/// first word of a line (usually the step number) is used as key.
+fn diff_views<T : Display>(before: T, after: T) -> Vec<Change> {
+    let before = format!("{}", before);
+    let after = format!("{}", after);
+
+    if before == after {
+        return vec![]
+    }
+
+    let before_lines : Vec<&str> = before.lines().collect();
+    let after_lines : Vec<&str> = after.lines().collect();
+
+    let mut changes = vec![];
+    for i in 0 .. before_lines.len().max(after_lines.len()) {
+        let b = before_lines.get(i).map_or("", |l| l.trim());
+        let a = after_lines.get(i).map_or("", |l| l.trim());
+
+        if a == b { continue }
+
+        let key = b.split_whitespace().next()
+            .or_else(|| a.split_whitespace().next())
+            .unwrap_or("")
+            .to_string();
+
+        changes.push(Change {
+            key: key.clone(),
+            before: b.strip_prefix(key.as_str()).unwrap_or(b).trim().to_string(),
+            after: a.strip_prefix(key.as_str()).unwrap_or(a).trim().to_string()
+        });
+    }
+
+    changes
+}
+
+fn diff_describable<T : Describable>(before: &T, before_ver: Version, after: &T, after_ver: Version) -> Vec<Change> {
+    diff_params(&FlatParams::of(before, before_ver), &FlatParams::of(after, after_ver))
+}
+
+fn song_metadata(song: &Song) -> Vec<(String, String)> {
+    vec![
+        ("VERSION".into(), format!("{}", song.version)),
+        ("NAME".into(), song.name.clone()),
+        ("DIRECTORY".into(), song.directory.clone()),
+        ("TEMPO".into(), format!("{}", song.tempo)),
+        ("TRANSPOSE".into(), format!("{:02X}", song.transpose)),
+        ("KEY".into(), format!("{:02X}", song.key)),
+        ("QUANTIZE".into(), format!("{:02X}", song.quantize)),
+    ]
+}
+
+fn song_grid_changes(before: &Song, after: &Song) -> Vec<Change> {
+    let cell = |song: &Song, row, track| {
+        arrangement::chain_at(song, row, track).map_or_else(|| "--".to_string(), |c| format!("{:02X}", c))
+    };
+
+    let mut changes = vec![];
+    for row in 0 .. SONG_ROW_COUNT {
+        for track in 0 .. TRACK_COUNT {
+            let b = cell(before, row, track);
+            let a = cell(after, row, track);
+            if a != b {
+                changes.push(Change { key: format!("{:02X}:{}", row, track + 1), before: b, after: a });
+            }
+        }
+    }
+
+    changes
+}
+
+/// Compute all the differences between two songs, elements without
+/// changes are not reported.
+pub fn diff_songs(before: &Song, after: &Song) -> Vec<ElementDiff> {
+    let mut diffs = vec![];
+    let mut push = |kind, id, changes: Vec<Change>| {
+        if !changes.is_empty() {
+            diffs.push(ElementDiff { kind, id, changes })
+        }
+    };
+
+    push("META", None, diff_params(&song_metadata(before), &song_metadata(after)));
+    push("SONG", None, song_grid_changes(before, after));
+
+    for i in 0 .. before.chains.len().min(after.chains.len()) {
+        push("CHAIN", Some(i), diff_views(&before.chains[i], &after.chains[i]));
+    }
+
+    for i in 0 .. before.phrases.len().min(after.phrases.len()) {
+        push("PHRASE", Some(i), diff_views(before.phrase_view(i), after.phrase_view(i)));
+    }
+
+    for i in 0 .. before.instruments.len().min(after.instruments.len()) {
+        let (b, a) = (&before.instruments[i], &after.instruments[i]);
+        let mut changes = diff_params(
+            &[("KIND".into(), instrument_kind(b).into())],
+            &[("KIND".into(), instrument_kind(a).into())]);
+        changes.extend(diff_describable(b, before.version, a, after.version));
+        push("INSTRUMENT", Some(i), changes);
+    }
+
+    for i in 0 .. before.tables.len().min(after.tables.len()) {
+        push("TABLE", Some(i), diff_views(before.table_view(i), after.table_view(i)));
+    }
+
+    for i in 0 .. before.eqs.len().min(after.eqs.len()) {
+        push("EQ", Some(i), diff_describable(&before.eqs[i], before.version, &after.eqs[i], after.version));
+    }
+
+    push("MIXER", None, diff_describable(&before.mixer_settings, before.version, &after.mixer_settings, after.version));
+    push("EFFECTS", None, diff_describable(&before.effects_settings, before.version, &after.effects_settings, after.version));
+
+    diffs
+}
+
+fn print_diffs(format: DiffFormat, diffs: &[ElementDiff], w: &mut dyn std::io::Write) -> std::io::Result<()> {
+    for diff in diffs {
+        match format {
+            DiffFormat::Text => {
+                writeln!(w, "{} {}", diff.kind, diff.id_str())?;
+                for change in diff.changes.iter() {
+                    if !change.before.is_empty() {
+                        writeln!(w, "  - {} : {}", change.key, change.before)?;
+                    }
+                    if !change.after.is_empty() {
+                        writeln!(w, "  + {} : {}", change.key, change.after)?;
+                    }
+                }
+            }
+            DiffFormat::Tsv => {
+                for change in diff.changes.iter() {
+                    writeln!(w, "{}\t{}\t{}\t{}\t{}",
+                        diff.kind, diff.id_str(), change.key, change.before, change.after)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare two song files, report findings if they differ.
+pub fn diff_song_files(format: DiffFormat, before: &str, after: &str, w: &mut dyn std::io::Write) -> Result<Outcome, M8FstoErr> {
+    let (before_song, _) = read_song_file(Path::new(before))?;
+    let (after_song, _) = read_song_file(Path::new(after))?;
+
+    let diffs = diff_songs(&before_song, &after_song);
+
+    if format == DiffFormat::Text {
+        writeln!(w, "--- {}", before).map_err(|_| M8FstoErr::PrintError)?;
+        writeln!(w, "+++ {}", after).map_err(|_| M8FstoErr::PrintError)?;
+    }
+
+    print_diffs(format, &diffs, w).map_err(|_| M8FstoErr::PrintError)?;
+    Ok(Outcome::findings_if(!diffs.is_empty()))
+}
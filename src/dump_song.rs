@@ -0,0 +1,558 @@
+use std::{fs, path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::{
+    reader::Reader, writer::Writer, AHDEnv, ADSREnv, AnalogInputSettings, CCEntry,
+    Chain, ChainStep, ControlChange, DrumEnv, EffectsSettings, EqBand, EqModeType, Equ,
+    ExternalInst, FMSynth, Groove, HyperSynth, InputMixerSettings, Instrument,
+    InstrumentWithEq, LfoShape, MIDIOut, MacroSynth, MidiMapping, MidiSettings,
+    MixerSettings, Mod, Note, NoteOffset, Operator, Phrase, Sampler, Scale, Song,
+    SongSteps, Step, SynthParams, Table, TableStep, TrackingEnv, TrigEnv, Version,
+    WavSynth, FX, LFO
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    arrangement::{EMPTY, SONG_ROW_COUNT, TRACK_COUNT},
+    instrument_file::{check_round_trip, file_header, instrument_file_blob, HEADER_SIZE},
+    show_song::instrument_kind,
+    types::M8FstoErr
+};
+
+/// Identifier of the JSON dumps
+const DUMP_FORMAT : &str = "m8fsto";
+
+/// Version of the JSON layout, to be increased on incompatible changes
+const DUMP_FORMAT_VERSION : u64 = 2;
+
+/// Navigation in the loaded JSON, keeping track of the location
+/// for error messages.
+#[derive(Clone, Copy)]
+struct At<'a> {
+    value: &'a Value,
+    path: &'a str
+}
+
+fn invalid(path: &str, expected: &str) -> M8FstoErr {
+    M8FstoErr::InvalidDump { reason: format!("{}: expected {}", path, expected) }
+}
+
+impl<'a> At<'a> {
+    fn field<R>(self, key: &str, f: impl FnOnce(At) -> Result<R, M8FstoErr>) -> Result<R, M8FstoErr> {
+        let path = format!("{}.{}", self.path, key);
+        let value = self.value.get(key).ok_or_else(|| invalid(&path, "a value"))?;
+        f(At { value, path: &path })
+    }
+
+    /// Elements of an array of any length
+    fn elements<R>(self, mut f: impl FnMut(usize, At) -> Result<R, M8FstoErr>) -> Result<Vec<R>, M8FstoErr> {
+        let items = self.value
+            .as_array()
+            .ok_or_else(|| invalid(self.path, "an array"))?;
+
+        items.iter().enumerate().map(|(i, value)| {
+            let path = format!("{}[{}]", self.path, i);
+            f(i, At { value, path: &path })
+        }).collect()
+    }
+
+    fn items<R>(self, len: usize, f: impl FnMut(usize, At) -> Result<R, M8FstoErr>) -> Result<Vec<R>, M8FstoErr> {
+        match self.value.as_array() {
+            Some(a) if a.len() == len => self.elements(f),
+            _ => Err(invalid(self.path, &format!("an array of {} elements", len)))
+        }
+    }
+
+    fn byte(self) -> Result<u8, M8FstoErr> {
+        self.value
+            .as_u64()
+            .and_then(|v| u8::try_from(v).ok())
+            .ok_or_else(|| invalid(self.path, "a number between 0 and 255"))
+    }
+
+    /// byte where null represents an empty value
+    fn opt_byte(self) -> Result<u8, M8FstoErr> {
+        if self.value.is_null() { Ok(EMPTY) } else { self.byte() }
+    }
+
+    fn bool(self) -> Result<bool, M8FstoErr> {
+        self.value.as_bool().ok_or_else(|| invalid(self.path, "a boolean"))
+    }
+
+    fn str(self) -> Result<&'a str, M8FstoErr> {
+        self.value.as_str().ok_or_else(|| invalid(self.path, "a string"))
+    }
+
+    /// Check a value which only identifies the dump
+    fn expect(self, expected: &Value) -> Result<(), M8FstoErr> {
+        if self.value == expected {
+            Ok(())
+        } else {
+            Err(invalid(self.path, &expected.to_string()))
+        }
+    }
+}
+
+fn opt_byte(v: u8) -> Value {
+    if v == EMPTY { Value::Null } else { json!(v) }
+}
+
+/// Conversion of the parser elements to JSON and back. All the fields
+/// of the parser are written, so an element can be rebuilt from its
+/// JSON alone.
+trait Dump : Sized {
+    fn dump(&self) -> Value;
+    fn load(at: At) -> Result<Self, M8FstoErr>;
+}
+
+/// Implement `Dump` for a parser struct, every field being listed
+/// and written under its own name.
+macro_rules! dump_struct {
+    ($($t:ident { $($field:ident),* })*) => { $(
+        impl Dump for $t {
+            fn dump(&self) -> Value {
+                let mut fields = Map::new();
+                $( fields.insert(stringify!($field).to_string(), self.$field.dump()); )*
+                Value::Object(fields)
+            }
+
+            fn load(at: At) -> Result<Self, M8FstoErr> {
+                Ok($t { $( $field: at.field(stringify!($field), Dump::load)? ),* })
+            }
+        }
+    )* }
+}
+
+impl Dump for u8 {
+    fn dump(&self) -> Value { json!(self) }
+    fn load(at: At) -> Result<Self, M8FstoErr> { at.byte() }
+}
+
+impl Dump for bool {
+    fn dump(&self) -> Value { json!(self) }
+    fn load(at: At) -> Result<Self, M8FstoErr> { at.bool() }
+}
+
+impl Dump for f32 {
+    fn dump(&self) -> Value { json!(self) }
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        at.value.as_f64().map(|v| v as f32).ok_or_else(|| invalid(at.path, "a number"))
+    }
+}
+
+impl Dump for String {
+    fn dump(&self) -> Value { json!(self) }
+    fn load(at: At) -> Result<Self, M8FstoErr> { at.str().map(String::from) }
+}
+
+impl<T : Dump, const N : usize> Dump for [T; N] {
+    fn dump(&self) -> Value {
+        Value::Array(self.iter().map(Dump::dump).collect())
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        at.items(N, |_, v| T::load(v))?
+            .try_into()
+            .map_err(|_| invalid(at.path, &format!("an array of {} elements", N)))
+    }
+}
+
+impl<T : Dump> Dump for Vec<T> {
+    fn dump(&self) -> Value {
+        Value::Array(self.iter().map(Dump::dump).collect())
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        at.elements(|_, v| T::load(v))
+    }
+}
+
+impl Dump for Version {
+    fn dump(&self) -> Value { json!(format!("{}", self)) }
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        let parts : Vec<u8> = at.str()?
+            .split('.')
+            .map(|p| p.parse::<u8>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid(at.path, "a version like \"4.0.2\""))?;
+
+        match parts[..] {
+            [major, minor, patch] => Ok(Version { major, minor, patch }),
+            _ => Err(invalid(at.path, "a version like \"4.0.2\""))
+        }
+    }
+}
+
+impl Dump for FX {
+    fn dump(&self) -> Value { json!([self.command, self.value]) }
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        let v = at.items(2, |_, b| b.byte())?;
+        Ok(FX { command: v[0], value: v[1] })
+    }
+}
+
+impl Dump for Note {
+    fn dump(&self) -> Value { opt_byte(self.0) }
+    fn load(at: At) -> Result<Self, M8FstoErr> { at.opt_byte().map(Note) }
+}
+
+impl Dump for EqModeType {
+    fn dump(&self) -> Value { json!(self.0) }
+    fn load(at: At) -> Result<Self, M8FstoErr> { at.byte().map(EqModeType) }
+}
+
+impl Dump for LfoShape {
+    fn dump(&self) -> Value { json!(u8::from(*self)) }
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        LfoShape::try_from(at.byte()?).map_err(|_| invalid(at.path, "an LFO shape number"))
+    }
+}
+
+impl Dump for Step {
+    fn dump(&self) -> Value {
+        json!({
+            "note": self.note.dump(),
+            "velocity": opt_byte(self.velocity),
+            "instrument": opt_byte(self.instrument),
+            "fx": [self.fx1.dump(), self.fx2.dump(), self.fx3.dump()]
+        })
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        let [fx1, fx2, fx3] = at.field("fx", <[FX; 3]>::load)?;
+        Ok(Step {
+            note: at.field("note", Note::load)?,
+            velocity: at.field("velocity", |v| v.opt_byte())?,
+            instrument: at.field("instrument", |v| v.opt_byte())?,
+            fx1, fx2, fx3
+        })
+    }
+}
+
+impl Dump for ChainStep {
+    fn dump(&self) -> Value {
+        json!({ "phrase": opt_byte(self.phrase), "transpose": self.transpose })
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        Ok(ChainStep {
+            phrase: at.field("phrase", |v| v.opt_byte())?,
+            transpose: at.field("transpose", |v| v.byte())?
+        })
+    }
+}
+
+impl Dump for TableStep {
+    fn dump(&self) -> Value {
+        json!({
+            "transpose": self.transpose,
+            "velocity": opt_byte(self.velocity),
+            "fx": [self.fx1.dump(), self.fx2.dump(), self.fx3.dump()]
+        })
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        let [fx1, fx2, fx3] = at.field("fx", <[FX; 3]>::load)?;
+        Ok(TableStep {
+            transpose: at.field("transpose", |v| v.byte())?,
+            velocity: at.field("velocity", |v| v.opt_byte())?,
+            fx1, fx2, fx3
+        })
+    }
+}
+
+impl Dump for Groove {
+    fn dump(&self) -> Value {
+        json!({
+            "number": self.number,
+            "steps": self.steps.iter().map(|t| opt_byte(*t)).collect::<Vec<_>>()
+        })
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        let steps = at.field("steps", |s| s.items(16, |_, t| t.opt_byte()))?;
+        Ok(Groove {
+            number: at.field("number", |v| v.byte())?,
+            steps: steps.try_into().map_err(|_| invalid(at.path, "16 groove steps"))?
+        })
+    }
+}
+
+/// The song grid, as rows of tracks
+impl Dump for SongSteps {
+    fn dump(&self) -> Value {
+        Value::Array(self.steps
+            .chunks(TRACK_COUNT)
+            .take(SONG_ROW_COUNT)
+            .map(|row| Value::Array(row.iter().map(|c| opt_byte(*c)).collect()))
+            .collect())
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        let mut steps = [EMPTY; SONG_ROW_COUNT * TRACK_COUNT];
+        at.items(SONG_ROW_COUNT, |row, r| r.items(TRACK_COUNT, |track, c| {
+            steps[row * TRACK_COUNT + track] = c.opt_byte()?;
+            Ok(())
+        }))?;
+        Ok(SongSteps { steps })
+    }
+}
+
+/// Object of an element, with a "kind" field telling its variant
+fn tagged(kind: &str, value: Value) -> Value {
+    let mut fields = Map::new();
+    fields.insert("kind".to_string(), json!(kind));
+    if let Value::Object(rest) = value {
+        fields.extend(rest);
+    }
+    Value::Object(fields)
+}
+
+impl Dump for Mod {
+    fn dump(&self) -> Value {
+        match self {
+            Mod::AHDEnv(m) => tagged("AHDEnv", m.dump()),
+            Mod::ADSREnv(m) => tagged("ADSREnv", m.dump()),
+            Mod::DrumEnv(m) => tagged("DrumEnv", m.dump()),
+            Mod::LFO(m) => tagged("LFO", m.dump()),
+            Mod::TrigEnv(m) => tagged("TrigEnv", m.dump()),
+            Mod::TrackingEnv(m) => tagged("TrackingEnv", m.dump())
+        }
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        match at.field("kind", |v| v.str().map(String::from))?.as_str() {
+            "AHDEnv" => Ok(Mod::AHDEnv(AHDEnv::load(at)?)),
+            "ADSREnv" => Ok(Mod::ADSREnv(ADSREnv::load(at)?)),
+            "DrumEnv" => Ok(Mod::DrumEnv(DrumEnv::load(at)?)),
+            "LFO" => Ok(Mod::LFO(LFO::load(at)?)),
+            "TrigEnv" => Ok(Mod::TrigEnv(TrigEnv::load(at)?)),
+            "TrackingEnv" => Ok(Mod::TrackingEnv(TrackingEnv::load(at)?)),
+            _ => Err(invalid(at.path, "a modulator kind"))
+        }
+    }
+}
+
+impl Dump for Instrument {
+    fn dump(&self) -> Value {
+        let kind = instrument_kind(self);
+        match self {
+            Instrument::WavSynth(i) => tagged(kind, i.dump()),
+            Instrument::MacroSynth(i) => tagged(kind, i.dump()),
+            Instrument::Sampler(i) => tagged(kind, i.dump()),
+            Instrument::MIDIOut(i) => tagged(kind, i.dump()),
+            Instrument::FMSynth(i) => tagged(kind, i.dump()),
+            Instrument::HyperSynth(i) => tagged(kind, i.dump()),
+            Instrument::External(i) => tagged(kind, i.dump()),
+            Instrument::None => tagged(kind, Value::Null)
+        }
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        // the kind names are the ones of `instrument_kind`
+        match at.field("kind", |v| v.str().map(String::from))?.as_str() {
+            "None" => Ok(Instrument::None),
+            "WavSynth" => Ok(Instrument::WavSynth(WavSynth::load(at)?)),
+            "MacroSynth" => Ok(Instrument::MacroSynth(MacroSynth::load(at)?)),
+            "Sample" => Ok(Instrument::Sampler(Sampler::load(at)?)),
+            "MIDIOut" => Ok(Instrument::MIDIOut(MIDIOut::load(at)?)),
+            "FMSynth" => Ok(Instrument::FMSynth(FMSynth::load(at)?)),
+            "HyperSynth" => Ok(Instrument::HyperSynth(HyperSynth::load(at)?)),
+            "External" => Ok(Instrument::External(ExternalInst::load(at)?)),
+            _ => Err(invalid(at.path, "an instrument kind"))
+        }
+    }
+}
+
+impl Dump for AnalogInputSettings {
+    fn dump(&self) -> Value {
+        match self {
+            AnalogInputSettings::Stereo(input) => json!({ "stereo": input.dump() }),
+            AnalogInputSettings::DualMono((left, right)) =>
+                json!({ "dual_mono": [left.dump(), right.dump()] })
+        }
+    }
+
+    fn load(at: At) -> Result<Self, M8FstoErr> {
+        if at.value.get("stereo").is_some() {
+            return Ok(AnalogInputSettings::Stereo(at.field("stereo", InputMixerSettings::load)?))
+        }
+
+        let [left, right] = at.field("dual_mono", <[InputMixerSettings; 2]>::load)?;
+        Ok(AnalogInputSettings::DualMono((left, right)))
+    }
+}
+
+dump_struct! {
+    Phrase { steps }
+    Chain { steps }
+    Table { tick_rate, steps }
+    NoteOffset { enable, semitones, cents }
+    Scale { number, key, notes, name }
+    MidiSettings {
+        receive_sync, receive_transport, send_sync, send_transport,
+        record_note_channel, record_note_velocity, record_note_delay_kill_commands,
+        control_map_channel, song_row_cue_channel, track_input_channel,
+        track_input_intrument, track_input_program_change, track_input_mode
+    }
+    MidiMapping { channel, control_number, value, typ, param_index, min_value, max_value }
+    InputMixerSettings { volume, chorus, delay, reverb }
+    MixerSettings {
+        master_volume, master_limit, track_volume, chorus_volume, delay_volume,
+        reverb_volume, analog_input, usb_input, dj_filter, dj_peak, dj_filter_type
+    }
+    EffectsSettings {
+        chorus_mod_depth, chorus_mod_freq, chorus_reverb_send,
+        delay_hp, delay_lp, delay_time_l, delay_time_r, delay_feedback, delay_width, delay_reverb_send,
+        reverb_hp, reverb_lp, reverb_size, reverb_damping, reverb_mod_depth, reverb_mod_freq,
+        reverb_width, reverb_shimmer, ott_level
+    }
+    EqBand { mode, freq_fin, freq, level_fin, level, q }
+    Equ { low, mid, high }
+    AHDEnv { dest, amount, attack, hold, decay }
+    ADSREnv { dest, amount, attack, decay, sustain, release }
+    DrumEnv { dest, amount, peak, body, decay }
+    LFO { shape, dest, trigger_mode, freq, amount, retrigger }
+    TrigEnv { dest, amount, attack, hold, decay, src }
+    TrackingEnv { dest, amount, src, lval, hval }
+    SynthParams {
+        volume, pitch, fine_tune, filter_type, filter_cutoff, filter_res, amp, limit,
+        mixer_pan, mixer_dry, mixer_chorus, mixer_delay, mixer_reverb, associated_eq, mods
+    }
+    WavSynth { number, name, transpose, table_tick, synth_params, shape, size, mult, warp, scan }
+    MacroSynth { number, name, transpose, table_tick, synth_params, shape, timbre, color, degrade, redux }
+    Sampler {
+        number, name, transpose, table_tick, synth_params,
+        sample_path, play_mode, slice, start, loop_start, length, degrade
+    }
+    ControlChange { number, value }
+    MIDIOut {
+        number, name, transpose, table_tick,
+        port, channel, bank_select, program_change, song_row_cue, custom_cc
+    }
+    Operator { shape, ratio, ratio_fine, level, feedback, retrigger, mod_a, mod_b }
+    FMSynth { number, name, transpose, table_tick, synth_params, algo, operators, mod1, mod2, mod3, mod4 }
+    HyperSynth {
+        number, name, transpose, table_tick, synth_params,
+        default_chord, scale, shift, swarm, width, subosc, chords
+    }
+    CCEntry { number, value }
+    ExternalInst {
+        number, name, transpose, table_tick, synth_params,
+        input, port, channel, bank, program, cca, ccb, ccc, ccd
+    }
+    Song {
+        version, name, directory, tempo, transpose, key, quantize,
+        song, chains, phrases, tables, grooves, scales, instruments, eqs,
+        mixer_settings, effects_settings, midi_settings, midi_mappings
+    }
+}
+
+fn song_file_json(song: &Song, file_size: usize) -> Value {
+    json!({
+        "format": DUMP_FORMAT,
+        "format_version": DUMP_FORMAT_VERSION,
+        "kind": "song",
+        "file_size": file_size,
+        "song": song.dump()
+    })
+}
+
+fn instrument_file_json(instr_eq: &InstrumentWithEq) -> Value {
+    json!({
+        "format": DUMP_FORMAT,
+        "format_version": DUMP_FORMAT_VERSION,
+        "kind": "instrument",
+        "m8_version": instr_eq.version.dump(),
+        "instrument": instr_eq.instrument.dump(),
+        "table": instr_eq.table.dump(),
+        "eq": instr_eq.eq.as_ref().map_or(Value::Null, Dump::dump)
+    })
+}
+
+/// Print a song or instrument file as JSON
+pub fn dump_file(path: &str, w: &mut dyn std::io::Write) -> Result<(), M8FstoErr> {
+    let file_path = PathBuf::from(path);
+    let file_blob = fs::read(&file_path)
+        .map_err(|e| M8FstoErr::CannotReadFile { path: file_path.clone(), source: Arc::new(e) })?;
+
+    let file_size = file_blob.len();
+    let mut reader = Reader::new(file_blob);
+    let dump = match Song::read_from_reader(&mut reader) {
+        Ok(song) => song_file_json(&song, file_size),
+        Err(e) => {
+            reader.set_pos(0);
+            match Instrument::read_from_reader(&mut reader) {
+                Ok(instr_eq) => instrument_file_json(&instr_eq),
+                Err(_) => return Err(M8FstoErr::UnparseableM8File { path: file_path, source: Arc::new(e) })
+            }
+        }
+    };
+
+    serde_json::to_writer_pretty(&mut *w, &dump).map_err(|_| M8FstoErr::PrintError)?;
+    writeln!(w).map_err(|_| M8FstoErr::PrintError)
+}
+
+/// Write a song from its dump in a file of `file_size` bytes, then
+/// check that the written song parses back to the same dump.
+fn song_blob(root: At, out_path: &str) -> Result<Vec<u8>, M8FstoErr> {
+    let file_size = root.field("file_size", |v| v.value
+        .as_u64()
+        .map(|s| s as usize)
+        .filter(|s| *s > HEADER_SIZE)
+        .ok_or_else(|| invalid(v.path, "a file size")))?;
+
+    let song = root.field("song", Song::load)?;
+
+    let mut blob = file_header(song.version);
+    blob.resize(file_size, 0);
+
+    let mut writer = Writer::new(blob);
+    song.write(&mut writer)
+        .map_err(|reason| M8FstoErr::SongSerializationError { destination: out_path.to_string(), reason })?;
+    let blob = writer.finish();
+
+    let mut reader = Reader::new(blob.clone());
+    let written = Song::read_from_reader(&mut reader)
+        .map_err(|e| M8FstoErr::InvalidDump { reason: format!("the rebuilt song cannot be parsed: {}", e) })?;
+
+    if written.dump() != song.dump() {
+        return Err(M8FstoErr::InvalidDump { reason: "the rebuilt song does not parse back to the dump".to_string() })
+    }
+
+    Ok(blob)
+}
+
+/// Write an instrument file from its dump
+fn instrument_blob(root: At, out_path: &str) -> Result<Vec<u8>, M8FstoErr> {
+    let version = root.field("m8_version", Version::load)?;
+    let instrument = root.field("instrument", Instrument::load)?;
+    let table = root.field("table", Table::load)?;
+    let eq = root.field("eq", |v| if v.value.is_null() { Ok(None) } else { Equ::load(v).map(Some) })?;
+
+    let blob = instrument_file_blob(&file_header(version), version, &instrument, &table, eq.as_ref());
+    check_round_trip(Path::new(out_path), &blob)?;
+    Ok(blob)
+}
+
+/// Rebuild a song or instrument file from a JSON dump
+pub fn load_file(json_path: &str, out_path: &str) -> Result<(), M8FstoErr> {
+    let path = Path::new(json_path);
+    let content = fs::read(path)
+        .map_err(|e| M8FstoErr::CannotReadFile { path: path.to_path_buf(), source: Arc::new(e) })?;
+
+    let value : Value = serde_json::from_slice(&content)
+        .map_err(|e| M8FstoErr::InvalidDump { reason: format!("{}", e) })?;
+
+    let root = At { value: &value, path: "$" };
+    root.field("format", |v| v.expect(&json!(DUMP_FORMAT)))?;
+    root.field("format_version", |v| v.expect(&json!(DUMP_FORMAT_VERSION)))?;
+
+    let blob = match root.field("kind", |v| v.str().map(String::from))?.as_str() {
+        "song" => song_blob(root, out_path)?,
+        "instrument" => instrument_blob(root, out_path)?,
+        _ => return Err(invalid("$.kind", "\"song\" or \"instrument\""))
+    };
+
+    fs::write(out_path, blob)
+        .map_err(|e| M8FstoErr::FileWriteError { path: PathBuf::from(out_path), source: Arc::new(e) })
+}
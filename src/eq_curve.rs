@@ -0,0 +1,145 @@
+use std::f64::consts::PI;
+
+use m8_file_parser::{EqType, Equ};
+
+/// Lowest frequency of the plots, in Hz
+pub const MIN_FREQ : f64 = 20.0;
+
+/// Highest frequency of the plots, in Hz
+pub const MAX_FREQ : f64 = 20000.0;
+
+/// Sample rate used to compute the band filters
+const SAMPLE_RATE : f64 = 44100.0;
+
+/// Smallest Q used for the filters, a Q of 0 having no response
+const MIN_Q : f64 = 0.1;
+
+/// A band of an EQ, with its frequency in Hz and its gain in dB
+#[derive(Clone, Copy, Debug)]
+pub struct EqBand {
+    pub kind: EqType,
+    pub freq: f64,
+    pub gain: f64,
+    pub q: f64
+}
+
+impl EqBand {
+    /// Band saved in a song or instrument file. The frequency is saved in
+    /// Hz and the gain in hundredths of dB, both decoded by the parser, and
+    /// the Q in tenths. The stereo mode of the band is not plotted.
+    fn of(band: &m8_file_parser::EqBand) -> EqBand {
+        EqBand {
+            kind: band.mode.eq_type(),
+            freq: (band.frequency() as f64).clamp(MIN_FREQ, MAX_FREQ),
+            gain: band.gain(),
+            q: (band.q as f64 / 10.0).max(MIN_Q)
+        }
+    }
+}
+
+/// Low, mid and high bands of an EQ
+pub fn eq_bands(eq: &Equ) -> Vec<EqBand> {
+    [&eq.low, &eq.mid, &eq.high]
+        .into_iter()
+        .map(EqBand::of)
+        .collect()
+}
+
+/// Biquad coefficients `[b0, b1, b2, a0, a1, a2]` of a band, from the
+/// audio EQ cookbook formulas.
+fn coefficients(band: &EqBand) -> [f64; 6] {
+    let a = 10f64.powf(band.gain / 40.0);
+    let w0 = 2.0 * PI * band.freq / SAMPLE_RATE;
+    let (sin, cos) = w0.sin_cos();
+    let alpha = sin / (2.0 * band.q);
+    let shelf = 2.0 * a.sqrt() * alpha;
+
+    match band.kind {
+        EqType::Bell => [
+            1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a,
+            1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a
+        ],
+        EqType::LowShelf => [
+            a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+            (a + 1.0) + (a - 1.0) * cos + shelf,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - shelf
+        ],
+        EqType::HiShelf => [
+            a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+            (a + 1.0) - (a - 1.0) * cos + shelf,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - shelf
+        ],
+        EqType::LowCut => [
+            (1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0,
+            1.0 + alpha, -2.0 * cos, 1.0 - alpha
+        ],
+        EqType::HiCut => [
+            (1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0,
+            1.0 + alpha, -2.0 * cos, 1.0 - alpha
+        ],
+        EqType::BandPass => [
+            alpha, 0.0, -alpha,
+            1.0 + alpha, -2.0 * cos, 1.0 - alpha
+        ]
+    }
+}
+
+/// Gain of a band at a frequency, in dB
+fn band_response(band: &EqBand, freq: f64) -> f64 {
+    let [b0, b1, b2, a0, a1, a2] = coefficients(band);
+    let w = 2.0 * PI * freq / SAMPLE_RATE;
+    let (sin1, cos1) = w.sin_cos();
+    let (sin2, cos2) = (2.0 * w).sin_cos();
+
+    let num = (b0 + b1 * cos1 + b2 * cos2).powi(2) + (b1 * sin1 + b2 * sin2).powi(2);
+    let den = (a0 + a1 * cos1 + a2 * cos2).powi(2) + (a1 * sin1 + a2 * sin2).powi(2);
+
+    10.0 * (num / den).max(1e-12).log10()
+}
+
+/// Gain of the whole EQ at a frequency, in dB
+pub fn response(bands: &[EqBand], freq: f64) -> f64 {
+    bands.iter().map(|b| band_response(b, freq)).sum()
+}
+
+/// Frequency of a point of a plot `points` wide, on a log scale
+pub fn plot_freq(x: usize, points: usize) -> f64 {
+    MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(x as f64 / (points.max(2) - 1) as f64)
+}
+
+/// A labelled EQ response, as `(frequency, dB)` pairs
+pub struct EqCurve {
+    pub label: String,
+    pub points: Vec<(f64, f64)>
+}
+
+impl EqCurve {
+    /// Response of the EQ on `points` log spaced frequencies
+    pub fn of(label: String, bands: &[EqBand], points: usize) -> EqCurve {
+        let points = (0 .. points)
+            .map(|x| {
+                let freq = plot_freq(x, points);
+                (freq, response(bands, freq))
+            })
+            .collect();
+
+        EqCurve { label, points }
+    }
+}
+
+/// Range of the dB axis, symmetric and by steps of 6dB
+pub fn db_range(curves: &[EqCurve]) -> f64 {
+    let max = curves
+        .iter()
+        .flat_map(|c| c.points.iter())
+        .map(|(_, db)| db.abs())
+        .fold(0.0, f64::max);
+
+    ((max / 6.0).ceil() * 6.0).clamp(12.0, 48.0)
+}
@@ -0,0 +1,407 @@
+use std::{fs, path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::{reader::Reader, Equ, Instrument, Song};
+
+use crate::{
+    colorize::{ColorChoice, Rgb},
+    eq_curve::{db_range, eq_bands, EqBand, EqCurve, MAX_FREQ, MIN_FREQ},
+    types::M8FstoErr
+};
+
+/// Colors of the successive curves
+pub const CURVE_COLORS : [Rgb; 6] = [
+    Rgb(0x32, 0xEC, 0xFF),
+    Rgb(0xFF, 0x00, 0x8C),
+    Rgb(0x00, 0xFF, 0x00),
+    Rgb(0xFF, 0xFF, 0x00),
+    Rgb(0xFF, 0x80, 0x00),
+    Rgb(0xC0, 0x80, 0xFF)
+];
+
+const BACKGROUND : Rgb = Rgb(0x11, 0x11, 0x11);
+const GRID : Rgb = Rgb(0x33, 0x33, 0x33);
+const AXIS : Rgb = Rgb(0x66, 0x66, 0x66);
+
+/// Frequencies marked on the grid
+const GRID_FREQS : [f64; 8] = [50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0, 10000.0];
+
+/// Frequencies labelled in the terminal plot
+const LABEL_FREQS : [f64; 3] = [100.0, 1000.0, 10000.0];
+
+fn curve_color(i: usize) -> Rgb {
+    CURVE_COLORS[i % CURVE_COLORS.len()]
+}
+
+fn hex_color(c: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", c.0, c.1, c.2)
+}
+
+fn freq_label(freq: f64) -> String {
+    if freq >= 1000.0 { format!("{}k", freq / 1000.0) } else { format!("{}", freq) }
+}
+
+/// Horizontal position of a frequency, from 0 to 1
+fn x_ratio(freq: f64) -> f64 {
+    (freq / MIN_FREQ).ln() / (MAX_FREQ / MIN_FREQ).ln()
+}
+
+/// Vertical position of a gain, from 0 at the top to 1 at the bottom
+fn y_ratio(db: f64, range: f64) -> f64 {
+    (range - db.clamp(-range, range)) / (2.0 * range)
+}
+
+/// Gain of the curve at the column `x` of a plot `columns` wide
+fn sample(curve: &EqCurve, x: usize, columns: usize) -> f64 {
+    let last = curve.points.len().saturating_sub(1);
+    let index = x * last / (columns.max(2) - 1);
+    curve.points.get(index).map_or(0.0, |(_, db)| *db)
+}
+
+/// Plot labelled EQ curves in an SVG image
+pub fn svg(curves: &[EqCurve], width: usize, height: usize) -> String {
+    let (w, h) = (width as f64, height as f64);
+    let range = db_range(curves);
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"monospace\" font-size=\"10\">\n",
+        width, height, width, height));
+    out.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", width, height, hex_color(BACKGROUND)));
+
+    for freq in GRID_FREQS {
+        let x = x_ratio(freq) * w;
+        out.push_str(&format!("<line x1=\"{:.1}\" y1=\"0\" x2=\"{:.1}\" y2=\"{}\" stroke=\"{}\"/>\n", x, x, height, hex_color(GRID)));
+        out.push_str(&format!("<text x=\"{:.1}\" y=\"{}\" fill=\"#888\">{}</text>\n", x + 2.0, height - 2, freq_label(freq)));
+    }
+
+    let mut db = -range;
+    while db <= range {
+        let y = y_ratio(db, range) * h;
+        let stroke = if db == 0.0 { AXIS } else { GRID };
+        out.push_str(&format!("<line x1=\"0\" y1=\"{:.1}\" x2=\"{}\" y2=\"{:.1}\" stroke=\"{}\"/>\n", y, width, y, hex_color(stroke)));
+        out.push_str(&format!("<text x=\"2\" y=\"{:.1}\" fill=\"#888\">{:+}dB</text>\n", y - 2.0, db));
+        db += 6.0;
+    }
+
+    for (i, curve) in curves.iter().enumerate() {
+        let color = hex_color(curve_color(i));
+        let path : Vec<String> = curve.points
+            .iter()
+            .map(|(freq, db)| format!("{:.1},{:.1}", x_ratio(*freq) * w, y_ratio(*db, range) * h))
+            .collect();
+
+        out.push_str(&format!(
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"/>\n",
+            color, path.join(" ")));
+        out.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" fill=\"{}\" text-anchor=\"end\">{}</text>\n",
+            width - 4, 12 * (i + 1), color, curve.label.replace('&', "&amp;").replace('<', "&lt;")));
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Dots of a braille character, by row and column
+const BRAILLE_DOTS : [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Plot labelled EQ curves with braille characters, `width` and `height`
+/// being counted in characters. Curves get their own color when `colored`.
+pub fn braille(curves: &[EqCurve], width: usize, height: usize, colored: bool) -> String {
+    let (dots_w, dots_h) = (width * 2, height * 4);
+    let range = db_range(curves);
+    let dot_y = |db: f64| (y_ratio(db, range) * (dots_h - 1) as f64).round() as usize;
+
+    // dots of each cell, with the last curve drawn in it
+    let mut cells : Vec<(u8, usize)> = vec![(0, 0); width * height];
+    for (c, curve) in curves.iter().enumerate() {
+        let mut previous = None;
+        for x in 0 .. dots_w {
+            let y = dot_y(sample(curve, x, dots_w));
+            let (from, to) = match previous {
+                Some(p) if p < y => (p + 1, y),
+                Some(p) if p > y => (y, p - 1),
+                _ => (y, y)
+            };
+
+            for y in from ..= to {
+                let cell = &mut cells[(y / 4) * width + x / 2];
+                cell.0 |= BRAILLE_DOTS[y % 4][x % 2];
+                cell.1 = c;
+            }
+            previous = Some(y);
+        }
+    }
+
+    let zero_row = dot_y(0.0) / 4;
+    let mut out = String::new();
+    for row in 0 .. height {
+        let gutter =
+            if row == 0 { format!("{:+}dB", range) }
+            else if row == zero_row { "0dB".to_string() }
+            else if row == height - 1 { format!("{:+}dB", -range) }
+            else { String::new() };
+        out.push_str(&format!("{:>6} ", gutter));
+
+        for (dots, curve) in &cells[row * width .. (row + 1) * width] {
+            match *dots {
+                0 if row == zero_row => out.push('·'),
+                0 => out.push(' '),
+                dots => {
+                    let c = char::from_u32(0x2800 + dots as u32).unwrap_or(' ');
+                    if colored {
+                        let Rgb(r, g, b) = curve_color(*curve);
+                        out.push_str(&format!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, c));
+                    } else {
+                        out.push(c);
+                    }
+                }
+            }
+        }
+        out.push('\n');
+    }
+
+    let mut axis = vec![' '; width];
+    for freq in LABEL_FREQS {
+        let column = (x_ratio(freq) * (width - 1) as f64).round() as usize;
+        for (i, c) in freq_label(freq).chars().enumerate() {
+            if let Some(slot) = axis.get_mut(column + i) {
+                *slot = c;
+            }
+        }
+    }
+    out.push_str(&format!("{:>6} {}\n", "", axis.iter().collect::<String>().trim_end()));
+
+    for (i, curve) in curves.iter().enumerate() {
+        if colored {
+            let Rgb(r, g, b) = curve_color(i);
+            out.push_str(&format!("{:>6} \x1b[38;2;{};{};{}m━━\x1b[0m {}\n", "", r, g, b, curve.label));
+        } else {
+            out.push_str(&format!("{:>6} {}\n", "", curve.label));
+        }
+    }
+
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0 .. 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for d in data {
+        a = (a + *d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start ..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Encode an RGB image as PNG, with uncompressed deflate blocks
+fn encode_png(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for row in rgb.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks : Vec<&[u8]> = raw.chunks(0xFFFF).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        let len = block.len() as u16;
+        zlib.push(if i + 1 == blocks.len() { 1 } else { 0 });
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut out = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    png_chunk(&mut out, b"IHDR", &header);
+    png_chunk(&mut out, b"IDAT", &zlib);
+    png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// Plot EQ curves in a PNG image. There is no text, the curves have
+/// the same colors as in the other plots.
+pub fn png(curves: &[EqCurve], width: usize, height: usize) -> Vec<u8> {
+    let range = db_range(curves);
+    let mut rgb : Vec<u8> = [BACKGROUND.0, BACKGROUND.1, BACKGROUND.2].repeat(width * height);
+    let mut put = |x: usize, y: usize, c: Rgb| {
+        if x < width && y < height {
+            let i = (y * width + x) * 3;
+            rgb[i .. i + 3].copy_from_slice(&[c.0, c.1, c.2]);
+        }
+    };
+
+    for freq in GRID_FREQS {
+        let x = (x_ratio(freq) * width as f64) as usize;
+        (0 .. height).for_each(|y| put(x, y, GRID));
+    }
+
+    let mut db = -range;
+    while db <= range {
+        let y = (y_ratio(db, range) * (height - 1) as f64) as usize;
+        let color = if db == 0.0 { AXIS } else { GRID };
+        (0 .. width).for_each(|x| put(x, y, color));
+        db += 6.0;
+    }
+
+    for (i, curve) in curves.iter().enumerate() {
+        let color = curve_color(i);
+        let mut previous = None;
+        for x in 0 .. width {
+            let y = (y_ratio(sample(curve, x, width), range) * (height - 1) as f64).round() as usize;
+            let (from, to) = match previous {
+                Some(p) if p < y => (p, y),
+                Some(p) => (y, p),
+                None => (y, y)
+            };
+
+            // two pixels thick lines
+            for y in from ..= to {
+                put(x, y, color);
+                put(x, y + 1, color);
+            }
+            previous = Some(y);
+        }
+    }
+
+    encode_png(width, height, &rgb)
+}
+
+/// Size of the plots, in characters for the terminal
+const TERMINAL_SIZE : (usize, usize) = (64, 12);
+
+/// Size of the plots, in pixels for the images
+const IMAGE_SIZE : (usize, usize) = (640, 240);
+
+/// Bands of the EQs to plot, with their labels. Without selected EQ or
+/// instrument, the EQs used by the instruments are taken.
+fn selected_bands(path: &Path, eqs: &[usize], instruments: &[usize]) -> Result<Vec<(String, Vec<EqBand>)>, M8FstoErr> {
+    let blob = fs::read(path)
+        .map_err(|e| M8FstoErr::CannotReadFile { path: path.to_path_buf(), source: Arc::new(e) })?;
+
+    let mut reader = Reader::new(blob);
+    let song = match Song::read_from_reader(&mut reader) {
+        Ok(song) => song,
+        Err(e) => {
+            if !eqs.is_empty() || !instruments.is_empty() {
+                return Err(M8FstoErr::EqSelectionOnInstrument { path: path.to_path_buf() });
+            }
+
+            reader.set_pos(0);
+            let instr_eq = Instrument::read_from_reader(&mut reader)
+                .map_err(|_| M8FstoErr::UnparseableM8File { path: path.to_path_buf(), source: Arc::new(e) })?;
+
+            let label = instr_eq.instrument.name().unwrap_or("").to_string();
+            return Ok(instr_eq.eq
+                .map(|eq| vec![(label, eq_bands(&eq))])
+                .unwrap_or_default())
+        }
+    };
+
+    let eq_of = |eq: usize| song.eqs
+        .get(eq)
+        .map(eq_bands)
+        .ok_or(M8FstoErr::UnknownElement { kind: "EQ", id: eq });
+
+    let instrument_eq = |i: usize| song.instruments
+        .get(i)
+        .and_then(|instr| instr.equ())
+        .ok_or(M8FstoErr::UnknownElement { kind: "instrument EQ", id: i })
+        .map(|eq| (format!("{:02X} {} (EQ {:02X})", i, song.instruments[i].name().unwrap_or(""), eq), eq as usize));
+
+    let mut selected = vec![];
+    for eq in eqs {
+        selected.push((format!("EQ {:02X}", eq), eq_of(*eq)?));
+    }
+
+    for i in instruments {
+        let (label, eq) = instrument_eq(*i)?;
+        selected.push((label, eq_of(eq)?));
+    }
+
+    if eqs.is_empty() && instruments.is_empty() {
+        for i in 0 .. song.instruments.len() {
+            match instrument_eq(i) {
+                Ok((label, eq)) if song.eqs.get(eq).is_some_and(|e| !e.is_empty()) =>
+                    selected.push((label, eq_of(eq)?)),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Plot the frequency response of EQs of a song or instrument file, in
+/// the terminal or as SVG or PNG image.
+pub fn plot_eq(
+    path: &str,
+    eqs: &[usize],
+    instruments: &[usize],
+    svg_path: Option<&str>,
+    png_path: Option<&str>,
+    color: ColorChoice) -> Result<(), M8FstoErr> {
+
+    let selected = selected_bands(Path::new(path), eqs, instruments)?;
+    if selected.is_empty() {
+        println!("No EQ to plot");
+        return Ok(())
+    }
+
+    let curves_of = |points: usize| -> Vec<EqCurve> {
+        selected
+            .iter()
+            .map(|(label, bands)| EqCurve::of(label.clone(), bands, points))
+            .collect()
+    };
+
+    let write = |out: &str, data: &[u8]| fs::write(out, data)
+        .map_err(|e| M8FstoErr::FileWriteError { path: PathBuf::from(out), source: Arc::new(e) });
+
+    let (image_w, image_h) = IMAGE_SIZE;
+    if let Some(out) = svg_path {
+        write(out, svg(&curves_of(image_w), image_w, image_h).as_bytes())?;
+    }
+
+    if let Some(out) = png_path {
+        write(out, &png(&curves_of(image_w), image_w, image_h))?;
+    }
+
+    if svg_path.is_none() && png_path.is_none() {
+        let (w, h) = TERMINAL_SIZE;
+        print!("{}", braille(&curves_of(w * 2), w, h, color.enabled()));
+    }
+
+    Ok(())
+}
+
+/// Terminal plot of a single EQ, as printed by `show eq`
+pub fn terminal_plot(label: String, eq: &Equ, colored: bool) -> String {
+    let (w, h) = TERMINAL_SIZE;
+    braille(&[EqCurve::of(label, &eq_bands(eq), w * 2)], w, h, colored)
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use m8_file_parser::{param_gatherer::{Describable, ParameterGatherer}, Version};
+
+/// Parameter gatherer flattening the parameter tree of a `Describable`
+/// element into a list of "NEST.NAME" keys with their printed value.
+pub struct FlatParams {
+    prefix: String,
+    seen: HashMap<String, usize>,
+    pub params: Vec<(String, String)>
+}
+
+impl FlatParams {
+    pub fn new() -> FlatParams {
+        FlatParams { prefix: String::new(), seen: HashMap::new(), params: vec![] }
+    }
+
+    /// Gather all the parameters of an element
+    pub fn of<T : Describable>(elem: &T, ver: Version) -> Vec<(String, String)> {
+        elem.describe(FlatParams::new(), ver).params
+    }
+
+    fn push(mut self, name: &str, value: String) -> Self {
+        let key = format!("{}{}", self.prefix, name);
+
+        // Some names can be repeated in a nest, make them unique
+        let count = self.seen.entry(key.clone()).or_insert(0);
+        *count += 1;
+        let key = if *count > 1 { format!("{}#{}", key, count) } else { key };
+
+        self.params.push((key, value));
+        self
+    }
+}
+
+impl ParameterGatherer for FlatParams {
+    fn hex(self, name: &str, val: u8) -> Self {
+        self.push(name, format!("{:02X}", val))
+    }
+
+    fn bool(self, name: &str, val: bool) -> Self {
+        self.push(name, format!("{}", val))
+    }
+
+    fn float(self, name: &str, val: f64) -> Self {
+        self.push(name, format!("{}", val))
+    }
+
+    fn str(self, name: &str, val: &str) -> Self {
+        self.push(name, val.to_string())
+    }
+
+    fn enumeration(self, name: &str, hex: u8, val: &str) -> Self {
+        self.push(name, format!("{:X} {}", hex, val))
+    }
+
+    fn nest_f<F>(self, name: &str, f: F) -> Self
+        where F : FnOnce (Self) -> Self, Self : Sized {
+
+        let prefix = self.prefix.clone();
+        let nested_prefix = format!("{}{}.", prefix, name);
+        let nested = f(FlatParams { prefix: nested_prefix, ..self });
+
+        FlatParams { prefix, ..nested }
+    }
+}
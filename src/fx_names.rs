@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use m8_file_parser::{fx_command_names, CommandPack, FxCommands, Song, FX};
+
+use crate::arrangement::EMPTY;
+
+/// FX command playing a table
+pub const TABLE_FX : &str = "TBX";
+
+/// An effect used in a phrase or table step
+pub struct NamedFx {
+    /// Step of the phrase or table
+    pub row: usize,
+
+    /// FX column, from 0 to 2
+    pub column: usize,
+
+    /// Command name, like `DEL` or `TBX`
+    pub name: String,
+
+    pub value: u8
+}
+
+/// Name of an FX command. The base commands depend on the firmware
+/// version, the others on the instrument playing the step.
+fn command_name(base: FxCommands, instrument: CommandPack, fx: &FX) -> String {
+    base.try_render(fx.command)
+        .or_else(|| instrument.try_render(fx.command))
+        .map_or_else(|| format!("?{:02X}", fx.command), String::from)
+}
+
+fn push_step_fx(found: &mut Vec<NamedFx>, row: usize, fx: [FX; 3], base: FxCommands, instrument: CommandPack) {
+    for (column, fx) in fx.iter().enumerate().filter(|(_, fx)| fx.command != EMPTY) {
+        found.push(NamedFx { row, column, name: command_name(base, instrument, fx), value: fx.value });
+    }
+}
+
+/// Named effects of a phrase, a step without instrument using the
+/// commands of the last instrument of the phrase.
+pub fn phrase_fx(song: &Song, id: usize) -> Vec<NamedFx> {
+    let base = fx_command_names(song.version);
+    let mut instrument = CommandPack::default();
+    let mut found = vec![];
+
+    for (row, step) in song.phrases[id].steps.iter().enumerate() {
+        if let Some(instr) = song.instruments.get(step.instrument as usize) {
+            instrument = instr.instr_command_text(song.version);
+        }
+        push_step_fx(&mut found, row, step.all_fx(), base, instrument);
+    }
+
+    found
+}
+
+/// Named effects of a table, using the commands of the instrument
+/// with the same number.
+pub fn table_fx(song: &Song, id: usize) -> Vec<NamedFx> {
+    let base = fx_command_names(song.version);
+    let instrument = song.instruments
+        .get(id)
+        .map_or_else(CommandPack::default, |i| i.instr_command_text(song.version));
+
+    let mut found = vec![];
+    for (row, step) in song.tables[id].steps.iter().enumerate() {
+        push_step_fx(&mut found, row, step.all_fx(), base, instrument);
+    }
+
+    found
+}
+
+/// Number of uses of each FX command in the phrases and the tables of a song
+#[derive(Default)]
+pub struct FxCount {
+    pub phrases: usize,
+    pub tables: usize
+}
+
+pub fn fx_stats(song: &Song) -> BTreeMap<String, FxCount> {
+    let mut stats : BTreeMap<String, FxCount> = BTreeMap::new();
+
+    for p in (0 .. song.phrases.len()).filter(|p| !song.phrases[*p].is_empty()) {
+        for fx in phrase_fx(song, p) {
+            stats.entry(fx.name).or_default().phrases += 1;
+        }
+    }
+
+    for t in (0 .. song.tables.len()).filter(|t| !song.tables[*t].is_empty()) {
+        for fx in table_fx(song, t) {
+            stats.entry(fx.name).or_default().tables += 1;
+        }
+    }
+
+    stats
+}
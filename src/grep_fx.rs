@@ -0,0 +1,33 @@
+use std::path::Path;
+
+use glob::Pattern;
+
+use crate::{fx_names::{phrase_fx, table_fx}, ignore_rules::IgnoreRules, song_scan::scan_songs, types::{M8FstoErr, Outcome}};
+
+/// Find the phrase and table steps using an FX command whose name
+/// matches a glob pattern, like `DEL` or `FM*`.
+pub fn grep_fx(cwd: &Path, ignore: &IgnoreRules, pattern: &str, paths: &[String]) -> Result<Outcome, M8FstoErr> {
+    let pattern = Pattern::new(&pattern.to_uppercase())
+        .map_err(|_| M8FstoErr::InvalidSearchPattern { pattern: pattern.to_string() })?;
+
+    scan_songs(cwd, ignore, paths, |path, song| {
+        let rel_path = path.strip_prefix(cwd).unwrap_or(path).display().to_string();
+        let mut found = false;
+
+        for p in (0 .. song.phrases.len()).filter(|p| !song.phrases[*p].is_empty()) {
+            for fx in phrase_fx(&song, p).iter().filter(|fx| pattern.matches(&fx.name)) {
+                found = true;
+                println!("{}:PHRASE {:02X}:{:X} : {}{:02X}", rel_path, p, fx.row, fx.name, fx.value);
+            }
+        }
+
+        for t in (0 .. song.tables.len()).filter(|t| !song.tables[*t].is_empty()) {
+            for fx in table_fx(&song, t).iter().filter(|fx| pattern.matches(&fx.name)) {
+                found = true;
+                println!("{}:TABLE {:02X}:{:X} : {}{:02X}", rel_path, t, fx.row, fx.name, fx.value);
+            }
+        }
+
+        Ok(Outcome::findings_if(found))
+    })
+}
@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use glob::{MatchOptions, Pattern};
+use m8_file_parser::{Instrument, Version};
+
+use crate::{flat_params::FlatParams, ignore_rules::IgnoreRules, show_song::instrument_kind, song_scan::scan_songs, types::{M8FstoErr, Outcome}};
+
+/// Parameter predicate, `NAME=VALUE` where the value can use glob patterns
+#[derive(Clone)]
+pub struct ParamFilter {
+    name: String,
+    value: Pattern
+}
+
+/// Parse a glob pattern on instrument names
+pub fn parse_name_pattern(s: &str) -> Result<Pattern, String> {
+    Pattern::new(s).map_err(|e| format!("invalid pattern '{}' : {}", s, e))
+}
+
+/// Parse a `NAME=VALUE` parameter predicate
+pub fn parse_param_filter(s: &str) -> Result<ParamFilter, String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", s))?;
+
+    let value = Pattern::new(&value.trim().to_uppercase())
+        .map_err(|e| format!("invalid value pattern '{}' : {}", value, e))?;
+
+    Ok(ParamFilter { name: name.trim().to_uppercase(), value })
+}
+
+impl ParamFilter {
+    /// The name matches the full parameter key, like `MODS.LFO.SHAPE`,
+    /// or its last part, like `SHAPE`.
+    fn matches(&self, params: &[(String, String)]) -> bool {
+        params.iter().any(|(key, value)| {
+            let key = key.to_uppercase();
+            let short_key = key.rsplit('.').next().unwrap_or(&key);
+            (key == self.name || short_key == self.name) && self.value.matches(&value.trim().to_uppercase())
+        })
+    }
+}
+
+/// Criteria of the instrument search, all of them must match
+pub struct InstrumentFilter {
+    pub kind: Option<String>,
+    pub name: Option<Pattern>,
+    pub channel: Option<u8>,
+    pub program: Option<u8>,
+    pub shape: Option<u8>,
+    pub params: Vec<ParamFilter>
+}
+
+impl InstrumentFilter {
+    fn matches(&self, instr: &Instrument, ver: Version) -> bool {
+        if let Instrument::None = instr {
+            return false
+        }
+
+        let kind_ok = self.kind
+            .as_ref()
+            .is_none_or(|k| k.eq_ignore_ascii_case(instrument_kind(instr)));
+
+        let name_ok = self.name
+            .as_ref()
+            .is_none_or(|p| p.matches_with(instr.name().unwrap_or(""), MatchOptions {
+                case_sensitive: false,
+                ..MatchOptions::new()
+            }));
+
+        let (channel, program) = match instr {
+            Instrument::MIDIOut(m) => (Some(m.channel), Some(m.program_change)),
+            Instrument::External(e) => (Some(e.channel), Some(e.program)),
+            _ => (None, None)
+        };
+
+        let shape = match instr {
+            Instrument::WavSynth(ws) => Some(ws.shape),
+            Instrument::MacroSynth(ms) => Some(ms.shape),
+            _ => None
+        };
+
+        let midi_ok = self.channel.is_none_or(|c| channel == Some(c))
+            && self.program.is_none_or(|p| program == Some(p));
+        let shape_ok = self.shape.is_none_or(|s| shape == Some(s));
+
+        let params_ok = self.params.is_empty() || {
+            let params = FlatParams::of(instr, ver);
+            self.params.iter().all(|p| p.matches(&params))
+        };
+
+        kind_ok && name_ok && midi_ok && shape_ok && params_ok
+    }
+}
+
+/// Find the song instruments matching all the criteria of the filter
+pub fn grep_instrument(cwd: &Path, ignore: &IgnoreRules, filter: &InstrumentFilter, paths: &[String]) -> Result<Outcome, M8FstoErr> {
+    scan_songs(cwd, ignore, paths, |path, song| {
+        let rel_path = path.strip_prefix(cwd).unwrap_or(path);
+        let mut found = false;
+
+        for (i, instr) in song.instruments.iter().enumerate() {
+            if filter.matches(instr, song.version) {
+                found = true;
+                println!("{}:{:02X} {} : {}",
+                    rel_path.display(),
+                    i,
+                    instrument_kind(instr),
+                    instr.name().unwrap_or(""));
+            }
+        }
+
+        Ok(Outcome::findings_if(found))
+    })
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use m8_file_parser::{Note, Song};
+
+use crate::{arrangement::EMPTY, ignore_rules::IgnoreRules, midi_export::NOTE_OFF, song_scan::scan_songs, types::{M8FstoErr, Outcome}};
+
+/// Parse a space separated note sequence, like `C-4 D#4 G-4`, using
+/// the note names of the phrase view.
+fn parse_notes(s: &str) -> Option<Vec<u8>> {
+    let notes : Option<Vec<u8>> = s
+        .split_whitespace()
+        .map(|name| (0 .. NOTE_OFF).find(|n| Note(*n).to_string().eq_ignore_ascii_case(name)))
+        .collect();
+
+    notes.filter(|n| !n.is_empty())
+}
+
+/// Does a window of phrase notes match the searched sequence
+fn matches(window: &[(usize, Option<u8>)], notes: &[u8], transpose_invariant: bool) -> bool {
+    let found : Option<Vec<u8>> = window.iter().map(|(_, n)| *n).collect();
+    let Some(found) = found else { return false };
+
+    if transpose_invariant {
+        let offset = found[0] as i16 - notes[0] as i16;
+        found.iter().zip(notes.iter()).all(|(f, n)| *f as i16 - *n as i16 == offset)
+    } else {
+        found == notes
+    }
+}
+
+/// Starting rows of the note sequence in a phrase
+fn find_in_phrase(song: &Song, phrase: usize, notes: &[u8], transpose_invariant: bool, ignore_empty: bool) -> Vec<usize> {
+    let sequence : Vec<(usize, Option<u8>)> = song.phrases[phrase].steps
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| !ignore_empty || s.note.0 != EMPTY)
+        .map(|(row, s)| (row, Some(s.note.0).filter(|n| *n < NOTE_OFF)))
+        .collect();
+
+    sequence
+        .windows(notes.len())
+        .filter(|w| matches(w, notes, transpose_invariant))
+        .map(|w| w[0].0)
+        .collect()
+}
+
+/// Find the phrases containing a note sequence. The sequence must fit
+/// in a single phrase, it isn't searched across the phrases of a chain.
+pub fn grep_notes(
+    cwd: &Path,
+    ignore: &IgnoreRules,
+    notes: &str,
+    transpose_invariant: bool,
+    ignore_empty: bool,
+    paths: &[String]) -> Result<Outcome, M8FstoErr> {
+
+    let notes = parse_notes(notes)
+        .ok_or_else(|| M8FstoErr::InvalidSearchPattern { pattern: notes.to_string() })?;
+
+    scan_songs(cwd, ignore, paths, |path, song| {
+        let rel_path = path.strip_prefix(cwd).unwrap_or(path);
+        let mut found = false;
+
+        for p in (0 .. song.phrases.len()).filter(|p| !song.phrases[*p].is_empty()) {
+            for row in find_in_phrase(&song, p, &notes, transpose_invariant, ignore_empty) {
+                found = true;
+                println!("{}:PHRASE {:02X}:{:X}", rel_path.display(), p, row);
+            }
+        }
+
+        Ok(Outcome::findings_if(found))
+    })
+}
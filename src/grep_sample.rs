@@ -1,19 +1,24 @@
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 use glob::glob;
 use glob::Pattern;
 use m8_file_parser::{reader::*, Instrument};
 
-use crate::types::M8FstoErr;
+use crate::{ignore_rules::IgnoreRules, types::M8FstoErr};
 
-fn on_file_blob(cwd: &Path, pattern: &Pattern, path: &Path, data: Vec<u8>) -> Result<(), M8FstoErr> {
+fn on_file_blob(cwd: &Path, ignore: &IgnoreRules, pattern: &Pattern, path: &Path, data: Vec<u8>) -> Result<(), M8FstoErr> {
     let mut reader = Reader::new(data);
     let song = m8_file_parser::Song::read_from_reader(&mut reader)
         .map_err(|e| M8FstoErr::UnparseableM8File {
             path: path.to_path_buf(),
-            reason: format!("{:?}", e)
+            source: Arc::new(e)
         })?;
 
+    if ignore.is_version_ignored(&song.version) {
+        return Ok(())
+    }
+
     for (i, instr) in song.instruments.iter().enumerate() {
         match instr {
             Instrument::Sampler(sampler) if pattern.matches(&sampler.sample_path) => {
@@ -33,23 +38,24 @@ fn on_file_blob(cwd: &Path, pattern: &Pattern, path: &Path, data: Vec<u8>) -> Re
     Ok(())
 }
 
-fn on_dir(cwd: &Path, pattern: &Pattern, path: &str) -> Result<(), M8FstoErr> {
+fn on_dir(cwd: &Path, ignore: &IgnoreRules, pattern: &Pattern, path: &str) -> Result<(), M8FstoErr> {
     let mut errors = vec![];
 
     for entry in glob(path).expect("Failed to read glob pattern") {
         match entry {
             Err(e) => println!("{:?}", e),
+            Ok(path) if ignore.is_ignored(&path) => {}
             Ok(path) => {
                 let try_as_file = fs::read(&path);
                 match try_as_file {
                     Err(e) => {
                         errors.push(M8FstoErr::CannotReadFile {
                             path: path.to_path_buf(),
-                            reason: format!("{:?}", e)
+                            source: Arc::new(e)
                         })
                     }
                     Ok(file_blob) => {
-                        match on_file_blob(cwd, pattern,&path, file_blob) {
+                        match on_file_blob(cwd, ignore, pattern,&path, file_blob) {
                             Ok(()) => {}
                             Err(m8err) => errors.push(m8err),
                         }
@@ -59,55 +65,27 @@ fn on_dir(cwd: &Path, pattern: &Pattern, path: &str) -> Result<(), M8FstoErr> {
         }
     }
 
-    if errors.len() == 0 {
-        Ok(())
-    } else if errors.len() == 1 {
-        Err(errors[0].clone())
-    } else {
-        Err(M8FstoErr::MultiErrs { inner: errors })
-    }
+    M8FstoErr::from_file_errors(errors)
 }
 
 /// Try to list sample of a given path
-pub fn grep_sample(cwd: &Path, pattern: &str, path : &Option<String>) -> Result<(), M8FstoErr> {
+pub fn grep_sample(cwd: &Path, ignore: &IgnoreRules, pattern: &str, path : &Option<String>) -> Result<(), M8FstoErr> {
     let pat =
         glob::Pattern::new(pattern)
             .map_err(|e|
                 M8FstoErr::InvalidSearchPattern { pattern: format!("{:?}", e) })?;
 
-    let mut errors = vec![];
     match path {
-        None => {
-            match on_dir(cwd, &pat, "./") {
-                Ok(_) => {}
-                Err(e) => errors.push(e)
-            }
-        },
+        None => on_dir(cwd, ignore, &pat, "./"),
         Some(path) => {
             let try_as_file = fs::read(path);
             match try_as_file {
-                Err(_) => {
-                    match on_dir(cwd, &pat, path) {
-                        Ok(()) => {}
-                        Err(e) => errors.push(e)
-                    }
-                }
+                Err(_) => on_dir(cwd, ignore, &pat, path),
                 Ok(file_blob) => {
                     let as_path = Path::new(path);
-                    match on_file_blob(cwd, &pat, as_path, file_blob) {
-                        Ok(()) => {}
-                        Err(e) => errors.push(e)
-                    }
+                    on_file_blob(cwd, ignore, &pat, as_path, file_blob)
                 }
             }
         }
-    };
-
-    if errors.len() == 0 {
-        Ok(())
-    } else if errors.len() == 1 {
-        Err(errors[0].clone())
-    } else {
-        Err(M8FstoErr::MultiErrs { inner: errors })
     }
 }
\ No newline at end of file
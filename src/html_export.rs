@@ -0,0 +1,235 @@
+use std::{fs, path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::{param_gatherer::{Describable, ParameterGatherer}, Instrument, Song};
+
+use crate::{
+    arrangement::{self, EMPTY, TRACK_COUNT},
+    catalog::html_escape,
+    compact::reachability,
+    eq_curve::{self, EqCurve},
+    eq_plot,
+    show_song::{instrument_kind, ElemDisplay},
+    song_file::read_song_file,
+    types::M8FstoErr
+};
+
+const EQ_PLOT_WIDTH : usize = 480;
+const EQ_PLOT_HEIGHT : usize = 160;
+
+/// Parameter gatherer writing the parameter tree as HTML table rows,
+/// hex values getting a bar like in the `show` output.
+struct HtmlParams {
+    out: String,
+    indent: usize
+}
+
+impl HtmlParams {
+    fn with_indent(self, indent: usize) -> Self {
+        HtmlParams { indent, ..self }
+    }
+
+    fn row(mut self, name: &str, value: &str, bar: Option<u8>) -> Self {
+        let bar = bar.map_or_else(String::new, |v|
+            format!("<div class=\"bar\"><div style=\"width: {:.1}%\"></div></div>", (v as f64 + 1.0) / 2.56));
+
+        self.out.push_str(&format!(
+            "<tr><td style=\"padding-left: {}em\">{}</td><td>{}</td><td>{}</td></tr>\n",
+            self.indent, html_escape(name), html_escape(value), bar));
+        self
+    }
+}
+
+impl ParameterGatherer for HtmlParams {
+    fn hex(self, name: &str, val: u8) -> Self {
+        self.row(name, &format!("{:02X}", val), Some(val))
+    }
+
+    fn bool(self, name: &str, val: bool) -> Self {
+        self.row(name, &format!("{}", val), None)
+    }
+
+    fn float(self, name: &str, val: f64) -> Self {
+        self.row(name, &format!("{}", val), None)
+    }
+
+    fn str(self, name: &str, val: &str) -> Self {
+        self.row(name, &format!("\"{}\"", val), None)
+    }
+
+    fn enumeration(self, name: &str, hex: u8, val: &str) -> Self {
+        self.row(name, &format!("{:X} {}", hex, val), None)
+    }
+
+    fn nest_f<F>(self, name: &str, f: F) -> Self
+        where F : FnOnce (Self) -> Self, Self : Sized {
+
+        let indent = self.indent;
+        let nested = f(self.row(name, "", None).with_indent(indent + 1));
+        nested.with_indent(indent)
+    }
+}
+
+fn chain_link(chain: usize) -> String {
+    format!("<a href=\"#chain-{:02X}\">{:02X}</a>", chain, chain)
+}
+
+fn phrase_link(phrase: usize) -> String {
+    format!("<a href=\"#phrase-{:02X}\">{:02X}</a>", phrase, phrase)
+}
+
+/// Link to an instrument section, only written for the slots holding
+/// an instrument.
+fn instrument_link(song: &Song, instrument: usize) -> String {
+    let name = song.instruments[instrument].name().unwrap_or("");
+    format!("<a href=\"#instrument-{:02X}\">{:02X} {}</a>", instrument, instrument, html_escape(name))
+}
+
+fn write_song_grid(song: &Song, out: &mut String) {
+    out.push_str("<h2 id=\"song\">Song</h2>\n<table class=\"grid\">\n");
+    out.push_str("<tr><th></th>");
+    for track in 1 ..= TRACK_COUNT {
+        out.push_str(&format!("<th>{}</th>", track));
+    }
+    out.push_str("</tr>\n");
+
+    let rows = arrangement::last_used_row(song).map_or(0, |r| r + 1);
+    for row in 0 .. rows {
+        out.push_str(&format!("<tr><th>{:02X}</th>", row));
+        for track in 0 .. TRACK_COUNT {
+            match arrangement::chain_at(song, row, track) {
+                None => out.push_str("<td class=\"empty\">--</td>"),
+                Some(chain) => out.push_str(&format!("<td>{}</td>", chain_link(chain)))
+            }
+        }
+        out.push_str("</tr>\n");
+    }
+
+    out.push_str("</table>\n");
+}
+
+fn write_chains(song: &Song, used: &[bool], out: &mut String) {
+    out.push_str("<h2 id=\"chains\">Chains</h2>\n");
+    for chain in (0 .. song.chains.len()).filter(|c| used[*c]) {
+        out.push_str(&format!("<h3 id=\"chain-{:02X}\">Chain {:02X}</h3>\n<table class=\"grid\">\n", chain, chain));
+        out.push_str("<tr><th></th><th>PH</th><th>TSP</th></tr>\n");
+        for (row, step) in song.chains[chain].steps.iter().enumerate() {
+            if step.phrase == EMPTY {
+                out.push_str(&format!("<tr><th>{:X}</th><td class=\"empty\">--</td><td class=\"empty\">00</td></tr>\n", row));
+            } else {
+                out.push_str(&format!(
+                    "<tr><th>{:X}</th><td>{}</td><td>{:02X}</td></tr>\n",
+                    row, phrase_link(step.phrase as usize), step.transpose));
+            }
+        }
+        out.push_str("</table>\n");
+    }
+}
+
+fn write_phrases(song: &Song, used: &[bool], out: &mut String) {
+    out.push_str("<h2 id=\"phrases\">Phrases</h2>\n");
+    for phrase in (0 .. song.phrases.len()).filter(|p| used[*p]) {
+        out.push_str(&format!("<h3 id=\"phrase-{:02X}\">Phrase {:02X}</h3>\n", phrase, phrase));
+        out.push_str(&format!("<pre>{}</pre>\n", html_escape(&song.phrase_view(phrase).to_string())));
+
+        let mut instruments : Vec<usize> = song.phrases[phrase].steps
+            .iter()
+            .map(|s| s.instrument as usize)
+            .filter(|i| song.instruments.get(*i).is_some_and(|instr| !matches!(instr, Instrument::None)))
+            .collect();
+        instruments.sort();
+        instruments.dedup();
+
+        if !instruments.is_empty() {
+            let links : Vec<String> = instruments.iter().map(|i| instrument_link(song, *i)).collect();
+            out.push_str(&format!("<p>Instruments: {}</p>\n", links.join(", ")));
+        }
+    }
+}
+
+fn write_instruments(song: &Song, out: &mut String) {
+    out.push_str("<h2 id=\"instruments\">Instruments</h2>\n");
+    for (id, instrument) in song.instruments.iter().enumerate() {
+        if matches!(instrument, Instrument::None) {
+            continue;
+        }
+
+        out.push_str(&format!(
+            "<h3 id=\"instrument-{:02X}\">{:02X} {} {}</h3>\n",
+            id, id, instrument_kind(instrument), html_escape(instrument.name().unwrap_or(""))));
+
+        let params = instrument.describe(HtmlParams { out: String::new(), indent: 0 }, song.version);
+        out.push_str(&format!("<table class=\"params\">\n{}</table>\n", params.out));
+
+        let eq = instrument.equ()
+            .and_then(|eq| song.eqs.get(eq as usize).map(|e| (eq, e)))
+            .filter(|(_, e)| !e.is_empty());
+
+        if let Some((eq_id, eq)) = eq {
+            let bands = eq_curve::eq_bands(eq);
+            let curve = EqCurve::of(format!("EQ {:02X}", eq_id), &bands, EQ_PLOT_WIDTH);
+            out.push_str(&format!("<h4>EQ {:02X}</h4>\n", eq_id));
+            out.push_str(&eq_plot::svg(&[curve], EQ_PLOT_WIDTH, EQ_PLOT_HEIGHT));
+            out.push_str(&format!("<pre>{}</pre>\n", html_escape(&ElemDisplay { instr: eq.clone(), ver: song.version }.to_string())));
+        }
+
+        if song.tables.get(id).is_some_and(|t| !t.is_empty()) {
+            out.push_str(&format!("<h4>Table {:02X}</h4>\n", id));
+            out.push_str(&format!("<pre>{}</pre>\n", html_escape(&song.table_view(id).to_string())));
+        }
+    }
+}
+
+fn write_samples(song: &Song, out: &mut String) {
+    out.push_str("<h2 id=\"samples\">Samples</h2>\n<table class=\"grid\">\n");
+    out.push_str("<tr><th>Instrument</th><th>Sample</th></tr>\n");
+    for (id, instrument) in song.instruments.iter().enumerate() {
+        if let Instrument::Sampler(s) = instrument {
+            out.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                instrument_link(song, id), html_escape(&s.sample_path)));
+        }
+    }
+    out.push_str("</table>\n");
+}
+
+/// Self contained HTML page describing a song
+pub fn song_page(song: &Song) -> String {
+    let reach = reachability(song);
+    let mut out = String::new();
+
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", html_escape(&song.name)));
+    out.push_str("<style>\n");
+    out.push_str("body { font-family: monospace; background: #111; color: #ddd; }\n");
+    out.push_str("a { color: #6cf; text-decoration: none; }\n");
+    out.push_str("h2, h3, h4 { color: #6cf; }\n");
+    out.push_str("table { border-collapse: collapse; }\n");
+    out.push_str("th, td { padding: 1px 6px; text-align: left; }\n");
+    out.push_str("th { color: #888; }\n");
+    out.push_str(".empty { color: #444; }\n");
+    out.push_str(".bar { width: 128px; height: 8px; background: #333; }\n");
+    out.push_str(".bar div { height: 8px; background: #6cf; }\n");
+    out.push_str("</style>\n</head>\n<body>\n");
+
+    out.push_str(&format!("<h1>{}</h1>\n", html_escape(&song.name)));
+    out.push_str(&format!("<p>Firmware {}, tempo {:.2}</p>\n", song.version, song.tempo));
+    out.push_str("<p><a href=\"#song\">Song</a> | <a href=\"#chains\">Chains</a> | <a href=\"#phrases\">Phrases</a> | ");
+    out.push_str("<a href=\"#instruments\">Instruments</a> | <a href=\"#samples\">Samples</a></p>\n");
+
+    write_song_grid(song, &mut out);
+    write_chains(song, &reach.chains, &mut out);
+    write_phrases(song, &reach.phrases, &mut out);
+    write_instruments(song, &mut out);
+    write_samples(song, &mut out);
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Export a song as a self contained HTML page
+pub fn export_html(song_path: &str, out_path: &str) -> Result<(), M8FstoErr> {
+    let (song, _) = read_song_file(Path::new(song_path))?;
+
+    fs::write(out_path, song_page(&song))
+        .map_err(|e| M8FstoErr::FileWriteError { path: PathBuf::from(out_path), source: Arc::new(e) })
+}
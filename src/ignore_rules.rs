@@ -0,0 +1,177 @@
+use std::{fs, path::{Component, Path, PathBuf}, sync::Arc};
+use glob::{MatchOptions, Pattern};
+use m8_file_parser::Version;
+
+use crate::types::M8FstoErr;
+
+/// Name of the ignore file looked up at the root of the backup
+pub const IGNORE_FILE_NAME : &str = ".m8fstoignore";
+
+/// One line of an ignore file, using gitignore syntax.
+struct IgnoreRule {
+    pattern: Pattern,
+
+    /// Rule starting with '!', re-including a previously
+    /// ignored file.
+    negated: bool,
+
+    /// Rule ending with '/', only matching folders
+    dir_only: bool,
+
+    /// Rule containing a '/', matched against the path relative
+    /// to the root instead of the file name.
+    anchored: bool
+}
+
+const MATCH_OPTIONS : MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false
+};
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Result<Option<IgnoreRule>, M8FstoErr> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None)
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line))
+        };
+
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line)
+        };
+
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+
+        let pattern = Pattern::new(line)
+            .map_err(|e| M8FstoErr::InvalidSearchPattern { pattern: format!("{} ({})", line, e) })?;
+
+        Ok(Some(IgnoreRule { pattern, negated, dir_only, anchored }))
+    }
+
+    fn matches(&self, rel_path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false
+        }
+
+        if self.anchored {
+            self.pattern.matches_with(rel_path, MATCH_OPTIONS)
+        } else {
+            let file_name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+            self.pattern.matches_with(file_name, MATCH_OPTIONS)
+        }
+    }
+}
+
+/// Rules used to skip files and folders while scanning for songs,
+/// read from the `.m8fstoignore` file at the root and from the
+/// `--exclude` command line arguments.
+pub struct IgnoreRules {
+    root: PathBuf,
+    rules: Vec<IgnoreRule>,
+
+    /// Songs made with an older firmware are skipped
+    min_version: Option<MinVersion>
+}
+
+/// Firmware version, as `major.minor.patch`, below which songs are skipped
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MinVersion(pub u8, pub u8, pub u8);
+
+/// Parse a firmware version like `4`, `4.0` or `3.1.2`
+pub fn parse_min_version(s: &str) -> Result<MinVersion, String> {
+    let parts : Vec<u8> = s
+        .split('.')
+        .map(|p| p.trim().parse::<u8>().map_err(|_| format!("invalid version '{}'", s)))
+        .collect::<Result<_, _>>()?;
+
+    match parts[..] {
+        [major] => Ok(MinVersion(major, 0, 0)),
+        [major, minor] => Ok(MinVersion(major, minor, 0)),
+        [major, minor, patch] => Ok(MinVersion(major, minor, patch)),
+        _ => Err(format!("invalid version '{}'", s))
+    }
+}
+
+impl IgnoreRules {
+    /// Rules ignoring nothing.
+    pub fn empty(root: &Path) -> IgnoreRules {
+        IgnoreRules { root: root.to_path_buf(), rules: vec![], min_version: None }
+    }
+
+    /// Load the ignore file at the root if any, command line
+    /// excludes are applied after the rules of the file.
+    pub fn load(root: &Path, excludes: &[String]) -> Result<IgnoreRules, M8FstoErr> {
+        let mut ignore = IgnoreRules::empty(root);
+        let ignore_file = root.join(IGNORE_FILE_NAME);
+
+        if ignore_file.is_file() {
+            let content = fs::read_to_string(&ignore_file)
+                .map_err(|e| M8FstoErr::CannotReadFile {
+                    path: ignore_file.clone(),
+                    source: Arc::new(e)
+                })?;
+
+            for line in content.lines() {
+                ignore.rules.extend(IgnoreRule::parse(line)?);
+            }
+        }
+
+        for exclude in excludes {
+            ignore.rules.extend(IgnoreRule::parse(exclude)?);
+        }
+
+        Ok(ignore)
+    }
+
+    /// Also skip the songs made with a firmware older than `version`
+    pub fn with_min_version(self, min_version: Option<MinVersion>) -> IgnoreRules {
+        IgnoreRules { min_version, ..self }
+    }
+
+    /// Check if a song must be skipped because of its firmware version
+    pub fn is_version_ignored(&self, version: &Version) -> bool {
+        self.min_version
+            .is_some_and(|min| MinVersion(version.major, version.minor, version.patch) < min)
+    }
+
+    fn is_rel_path_ignored(&self, rel_path: &str, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|r| r.matches(rel_path, is_dir))
+            .is_some_and(|r| !r.negated)
+    }
+
+    /// Check if a file found during a scan must be skipped. Like git,
+    /// a file within an ignored folder cannot be re-included.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.rules.is_empty() {
+            return false
+        }
+
+        let rel_path = path.strip_prefix(&self.root).unwrap_or(path);
+        let components : Vec<String> = rel_path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(n) => Some(n.to_string_lossy().to_string()),
+                _ => None
+            })
+            .collect();
+
+        for i in 1..=components.len() {
+            let is_dir = i < components.len() || path.is_dir();
+            if self.is_rel_path_ignored(&components[..i].join("/"), is_dir) {
+                return true
+            }
+        }
+
+        false
+    }
+}
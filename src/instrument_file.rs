@@ -0,0 +1,294 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::{reader::Reader, writer::Writer, Equ, Instrument, InstrumentWithEq, Song, Table, Version};
+
+use crate::{broken_search::sample_to_absolute_path, move_samples::normalize_path, slots::{attach_eq, free_instruments, take_slots}, song_file::read_song_file, types::{FlagBag, M8FstoErr}};
+
+/// Size of the header shared by song and instrument files, holding the
+/// "M8VERSION" tag and the firmware version.
+pub const HEADER_SIZE : usize = 0x0E;
+
+/// Enough room for an instrument, its table and its EQ
+const INSTRUMENT_FILE_CAPACITY : usize = 0x400;
+
+/// Header of a song or instrument file made with a firmware version
+pub fn file_header(version: Version) -> Vec<u8> {
+    let mut header = b"M8VERSION\0".to_vec();
+    header.extend([(version.minor << 4) | version.patch, version.major, 0, 0]);
+    header
+}
+
+/// Build the content of an instrument file from an instrument, with
+/// its table and EQ, behind the header of the file it comes from.
+pub fn instrument_file_blob(header: &[u8], version: Version, instrument: &Instrument, table: &Table, eq: Option<&Equ>) -> Vec<u8> {
+    let mut blob = header[.. HEADER_SIZE].to_vec();
+    blob.resize(HEADER_SIZE + INSTRUMENT_FILE_CAPACITY, 0);
+
+    let mut writer = Writer::new(blob);
+    writer.seek(HEADER_SIZE);
+    instrument.write(version, &mut writer);
+    table.write(&mut writer);
+
+    if let Some(eq) = eq {
+        eq.write(&mut writer);
+    }
+
+    let end = writer.pos();
+    let mut blob = writer.finish();
+    blob.truncate(end);
+    blob
+}
+
+/// Check that an instrument file loads back with the parser, and that
+/// the loaded instrument, table and EQ give back the same bytes.
+pub fn check_round_trip(out_path: &Path, blob: &[u8]) -> Result<(), M8FstoErr> {
+    let round_trip_err = |reason: String|
+        M8FstoErr::InstrumentRoundTrip { path: out_path.to_path_buf(), reason };
+
+    let mut reader = Reader::new(blob.to_vec());
+    let loaded = Instrument::read_from_reader(&mut reader)
+        .map_err(|e| round_trip_err(format!("{}", e)))?;
+
+    let reloaded = instrument_file_blob(blob, loaded.version, &loaded.instrument, &loaded.table, loaded.eq.as_ref());
+    if reloaded != blob {
+        return Err(round_trip_err("the loaded instrument differs from the written one".to_string()))
+    }
+
+    Ok(())
+}
+
+/// Name usable as file name for an instrument
+fn instrument_file_name(instrument: &Instrument, id: usize) -> String {
+    let name : String = instrument
+        .name()
+        .unwrap_or("")
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    if name.is_empty() {
+        format!("INSTR_{:02X}.m8i", id)
+    } else {
+        format!("{}.m8i", name)
+    }
+}
+
+/// Copy the sample of a sampler instrument next to the instrument file,
+/// making the instrument point to the copy. `copied` keeps the samples
+/// already copied by file name, a sample shared by several instruments
+/// is copied once, and two samples with the same name are refused.
+fn copy_sample(
+    root: &Path,
+    song_path: &Path,
+    out_path: &Path,
+    instrument: &mut Instrument,
+    id: usize,
+    copied: &mut HashMap<String, PathBuf>) -> Result<(), M8FstoErr> {
+    let Instrument::Sampler(sampler) = instrument else { return Ok(()) };
+    if sampler.sample_path.is_empty() {
+        return Ok(())
+    }
+
+    let full_sample_path = sample_to_absolute_path(root, song_path, &sampler.sample_path);
+    if !full_sample_path.exists() {
+        return Err(M8FstoErr::MissingSample { instr: id, path: full_sample_path })
+    }
+
+    let file_name = full_sample_path.file_name().unwrap().to_string_lossy().to_string();
+    let out_sample_path = out_path.parent().unwrap_or(Path::new(".")).join(&file_name);
+
+    match copied.get(&file_name) {
+        Some(first) if *first == full_sample_path => {
+            sampler.sample_path = file_name;
+            return Ok(())
+        }
+        Some(first) => {
+            return Err(M8FstoErr::SampleNameClash {
+                name: file_name,
+                first: first.clone(),
+                second: full_sample_path
+            })
+        }
+        None => {}
+    }
+
+    fs::copy(&full_sample_path, &out_sample_path)
+        .map_err(|e| M8FstoErr::SampleCopyError {
+            path: full_sample_path.clone(),
+            to: out_sample_path,
+            source: Arc::new(e)
+        })?;
+
+    copied.insert(file_name.clone(), full_sample_path);
+    sampler.sample_path = file_name;
+    Ok(())
+}
+
+/// Write one instrument file, copying its sample when `copied_samples`
+/// is set.
+fn extract_one(
+    root: &Path,
+    song_path: &Path,
+    song: &Song,
+    song_blob: &[u8],
+    id: usize,
+    out_path: &Path,
+    copied_samples: Option<&mut HashMap<String, PathBuf>>) -> Result<(), M8FstoErr> {
+
+    let mut instrument = song.instruments[id].clone();
+    if let Some(copied) = copied_samples {
+        copy_sample(root, song_path, out_path, &mut instrument, id, copied)?;
+    }
+
+    let eq = instrument.equ().and_then(|eq| song.eqs.get(eq as usize));
+    let blob = instrument_file_blob(song_blob, song.version, &instrument, &song.tables[id], eq);
+    check_round_trip(out_path, &blob)?;
+
+    fs::write(out_path, blob)
+        .map_err(|e| M8FstoErr::FileWriteError { path: out_path.to_path_buf(), source: Arc::new(e) })?;
+
+    println!("Instrument {:02X} written to {:?}", id, out_path);
+    Ok(())
+}
+
+/// Write a song instrument, or all of them when `id` is not set, as
+/// standalone instrument files. With a single instrument `output` is the
+/// instrument file, otherwise it's the destination folder.
+pub fn extract_instrument(
+    root: &Path,
+    song_path: &str,
+    id: Option<u8>,
+    output: Option<&str>,
+    with_sample: bool) -> Result<(), M8FstoErr> {
+
+    let song_path = Path::new(song_path);
+    let (song, song_blob) = read_song_file(song_path)?;
+
+    match id {
+        Some(id) => {
+            let id = id as usize;
+            let instrument = match song.instruments.get(id) {
+                None | Some(Instrument::None) =>
+                    return Err(M8FstoErr::UnknownElement { kind: "instrument", id }),
+                Some(instrument) => instrument
+            };
+
+            let out_path = output.map_or_else(
+                || PathBuf::from(instrument_file_name(instrument, id)),
+                PathBuf::from);
+
+            extract_one(root, song_path, &song, &song_blob, id, &out_path, with_sample.then_some(&mut HashMap::new()))
+        }
+        None => {
+            let out_folder = PathBuf::from(output.unwrap_or("."));
+            fs::create_dir_all(&out_folder)
+                .map_err(|e| M8FstoErr::FolderCreationError { path: out_folder.clone(), source: Arc::new(e) })?;
+
+            let mut errors = vec![];
+            let mut copied = HashMap::new();
+            for (id, instrument) in song.instruments.iter().enumerate() {
+                if let Instrument::None = instrument {
+                    continue
+                }
+
+                let out_path = out_folder.join(format!("{:02X}_{}", id, instrument_file_name(instrument, id)));
+                if let Err(e) = extract_one(root, song_path, &song, &song_blob, id, &out_path, with_sample.then_some(&mut copied)) {
+                    errors.push(e)
+                }
+            }
+
+            M8FstoErr::from_file_errors(errors)
+        }
+    }
+}
+
+/// Read and parse an instrument file
+fn read_instrument_file(path: &Path) -> Result<InstrumentWithEq, M8FstoErr> {
+    let file_blob = fs::read(path)
+        .map_err(|e| M8FstoErr::CannotReadFile { path: path.to_path_buf(), source: Arc::new(e) })?;
+
+    let mut reader = Reader::new(file_blob);
+    Instrument::read_from_reader(&mut reader)
+        .map_err(|e| M8FstoErr::UnparseableM8File { path: path.to_path_buf(), source: Arc::new(e) })
+}
+
+fn absolute(path: &Path) -> Result<PathBuf, M8FstoErr> {
+    std::path::absolute(path)
+        .map(|p| normalize_path(&p))
+        .map_err(|_| M8FstoErr::InvalidPath { reason: format!("cannot resolve {:?}", path) })
+}
+
+/// Sample path of an imported instrument, as seen from the song: relative
+/// to the song if the sample is in the song folder, otherwise absolute from
+/// the root.
+fn song_sample_path(root: &Path, instrument_path: &Path, song_path: &Path, sample_path: &str) -> Result<String, M8FstoErr> {
+    let sample = absolute(&sample_to_absolute_path(root, instrument_path, sample_path))?;
+    let song_folder = absolute(song_path)?.parent().map(Path::to_path_buf).unwrap_or_default();
+    let to_m8 = |p: &Path| p.to_string_lossy().replace('\\', "/");
+
+    if let Ok(relative) = sample.strip_prefix(&song_folder) {
+        Ok(to_m8(relative))
+    } else if let Ok(relative) = sample.strip_prefix(absolute(root)?) {
+        Ok(format!("/{}", to_m8(relative)))
+    } else {
+        Err(M8FstoErr::InvalidPath {
+            reason: format!("sample {:?} is neither in the song folder nor in the root folder", sample)
+        })
+    }
+}
+
+/// Write an instrument file, with its table and EQ, in a song slot. The
+/// first free slot is used if `slot` is not set.
+pub fn import_instrument(
+    flags: FlagBag,
+    root: &Path,
+    instrument_path: &str,
+    song_path: &str,
+    slot: Option<u8>) -> Result<(), M8FstoErr> {
+
+    let instrument_path = Path::new(instrument_path);
+    let InstrumentWithEq { mut instrument, table, eq, .. } = read_instrument_file(instrument_path)?;
+    let (mut song, song_blob) = read_song_file(Path::new(song_path))?;
+
+    let slot = match slot {
+        Some(slot) => slot as usize,
+        None => take_slots(&free_instruments(&song), 1, "instrument")?[0]
+    };
+
+    let occupied = !matches!(song.instruments[slot], Instrument::None) || !song.tables[slot].is_empty();
+    if occupied && !flags.force {
+        return Err(M8FstoErr::SlotNotEmpty { kind: "instrument", id: slot })
+    }
+
+    if let Instrument::Sampler(sampler) = &mut instrument {
+        if !sampler.sample_path.is_empty() {
+            sampler.sample_path = song_sample_path(root, instrument_path, Path::new(song_path), &sampler.sample_path)?;
+        }
+    }
+
+    let eq_slot = attach_eq(&mut song, &mut instrument, eq)?;
+
+    song.instruments[slot] = instrument;
+    song.tables[slot] = table;
+
+    match eq_slot {
+        None => println!("Instrument written in slot {:02X}", slot),
+        Some(eq) => println!("Instrument written in slot {:02X}, using EQ {:02X}", slot, eq)
+    }
+
+    if flags.dry_run {
+        return Ok(())
+    }
+
+    let mut writer = Writer::new(song_blob);
+    song.write(&mut writer)
+        .map_err(|reason|
+            M8FstoErr::SongSerializationError {
+                destination: song_path.to_string(),
+                reason
+            })?;
+
+    fs::write(song_path, writer.finish())
+        .map_err(|e| M8FstoErr::FileWriteError { path: PathBuf::from(song_path), source: Arc::new(e) })
+}
@@ -0,0 +1,144 @@
+use std::{collections::BTreeSet, path::Path};
+
+use m8_file_parser::{Instrument, Song};
+
+use crate::{arrangement::{EMPTY, SONG_ROW_COUNT, TRACK_COUNT}, broken_search::sample_to_absolute_path, fx_names::{phrase_fx, table_fx, TABLE_FX}, ignore_rules::IgnoreRules, show_song::InstrumentCounter, song_scan::scan_songs, types::{M8FstoErr, Outcome}};
+
+/// A problem found in a song
+struct Finding {
+    /// Short identifier of the check, stable for scripts
+    check: &'static str,
+
+    /// Element of the song, like `PHRASE 0A:3`
+    element: String,
+
+    message: String
+}
+
+fn instrument_name(song: &Song, id: usize) -> &str {
+    song.instruments.get(id).and_then(|i| i.name()).unwrap_or("")
+}
+
+fn lint_song(root: &Path, song_path: &Path, song: &Song) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    for (p, phrase) in song.phrases.iter().enumerate() {
+        for (row, step) in phrase.steps.iter().enumerate() {
+            let used_empty = step.instrument != EMPTY
+                && matches!(song.instruments.get(step.instrument as usize), Some(Instrument::None));
+
+            if used_empty {
+                findings.push(Finding {
+                    check: "empty-instrument",
+                    element: format!("PHRASE {:02X}:{:X}", p, row),
+                    message: format!("uses empty instrument {:02X}", step.instrument)
+                });
+            }
+        }
+    }
+
+    for (i, instr) in song.instruments.iter().enumerate() {
+        let Instrument::Sampler(sampler) = instr else { continue };
+
+        if sampler.sample_path.is_empty() {
+            findings.push(Finding {
+                check: "empty-sample-path",
+                element: format!("INSTRUMENT {:02X}", i),
+                message: format!("sampler '{}' has no sample", sampler.name)
+            });
+        } else if !sample_to_absolute_path(root, song_path, &sampler.sample_path).exists() {
+            findings.push(Finding {
+                check: "missing-sample",
+                element: format!("INSTRUMENT {:02X}", i),
+                message: format!("sample '{}' not found", sampler.sample_path)
+            });
+        }
+    }
+
+    let grid_chains : BTreeSet<usize> = song.song.steps
+        .iter()
+        .take(SONG_ROW_COUNT * TRACK_COUNT)
+        .filter(|c| **c != EMPTY)
+        .map(|c| *c as usize)
+        .collect();
+
+    for chain in grid_chains {
+        if song.chains.get(chain).is_some_and(|c| c.is_empty()) {
+            findings.push(Finding {
+                check: "empty-chain",
+                element: format!("CHAIN {:02X}", chain),
+                message: "on the song grid but empty".into()
+            });
+        }
+    }
+
+    let counter = song.instruments.iter().fold(InstrumentCounter::default(), |acc, i| acc.count(i));
+    for (channel, _) in counter.used_midi_channel.iter().filter(|(_, count)| **count > 1) {
+        let users : Vec<String> = song.instruments
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| match i {
+                Instrument::MIDIOut(m) => m.channel == *channel,
+                Instrument::External(e) => e.channel == *channel,
+                _ => false
+            })
+            .map(|(id, _)| format!("{:02X}", id))
+            .collect();
+
+        findings.push(Finding {
+            check: "shared-midi-channel",
+            element: format!("MIDI CHANNEL {}", channel),
+            message: format!("used by instruments {}", users.join(" "))
+        });
+    }
+
+    for (i, instr) in song.instruments.iter().enumerate() {
+        let Some(eq) = instr.equ() else { continue };
+        if song.eqs.get(eq as usize).is_some_and(|e| e.is_empty()) {
+            findings.push(Finding {
+                check: "flat-eq",
+                element: format!("INSTRUMENT {:02X}", i),
+                message: format!("'{}' uses flat EQ {:02X}", instrument_name(song, i), eq)
+            });
+        }
+    }
+
+    let mut table_users = vec![];
+    for p in (0 .. song.phrases.len()).filter(|p| !song.phrases[*p].is_empty()) {
+        for fx in phrase_fx(song, p).into_iter().filter(|fx| fx.name == TABLE_FX) {
+            table_users.push((format!("PHRASE {:02X}:{:X}", p, fx.row), fx.value));
+        }
+    }
+
+    for t in (0 .. song.tables.len()).filter(|t| !song.tables[*t].is_empty()) {
+        for fx in table_fx(song, t).into_iter().filter(|fx| fx.name == TABLE_FX) {
+            table_users.push((format!("TABLE {:02X}:{:X}", t, fx.row), fx.value));
+        }
+    }
+
+    for (element, table) in table_users {
+        if song.tables.get(table as usize).is_some_and(|t| t.is_empty()) {
+            findings.push(Finding {
+                check: "empty-table",
+                element,
+                message: format!("{} plays empty table {:02X}", TABLE_FX, table)
+            });
+        }
+    }
+
+    findings
+}
+
+/// Check songs for suspicious content, printing one tab separated
+/// line per finding: song path, check, element and message.
+pub fn lint(root: &Path, ignore: &IgnoreRules, paths: &[String], w: &mut dyn std::io::Write) -> Result<Outcome, M8FstoErr> {
+    scan_songs(root, ignore, paths, |song_path, song| {
+        let findings = lint_song(root, song_path, &song);
+        for f in findings.iter() {
+            writeln!(w, "{}\t{}\t{}\t{}", song_path.display(), f.check, f.element, f.message)
+                .map_err(|_| M8FstoErr::PrintError)?;
+        }
+
+        Ok(Outcome::findings_if(!findings.is_empty()))
+    })
+}
@@ -1,17 +1,21 @@
-use std::{fs, path::Path};
+use std::{fs, path::Path, sync::Arc};
 use glob::glob;
 use m8_file_parser::{reader::*, Instrument};
 
-use crate::types::M8FstoErr;
+use crate::{ignore_rules::IgnoreRules, types::M8FstoErr};
 
-fn on_file_blob(cwd: &Path, path: &Path, data: Vec<u8>) -> Result<(), M8FstoErr> {
+fn on_file_blob(cwd: &Path, ignore: &IgnoreRules, path: &Path, data: Vec<u8>) -> Result<(), M8FstoErr> {
     let mut reader = Reader::new(data);
     let song = m8_file_parser::Song::read_from_reader(&mut reader)
         .map_err(|e| M8FstoErr::UnparseableM8File {
             path: path.to_path_buf(),
-            reason: format!("{:?}", e)
+            source: Arc::new(e)
         })?;
 
+    if ignore.is_version_ignored(&song.version) {
+        return Ok(())
+    }
+
     let mut has_seen_sample = false;
     for (i, instr) in song.instruments.iter().enumerate() {
         match instr {
@@ -36,24 +40,25 @@ fn on_file_blob(cwd: &Path, path: &Path, data: Vec<u8>) -> Result<(), M8FstoErr>
     Ok(())
 }
 
-fn on_dir(cwd: &Path, path: &str) -> Result<(), M8FstoErr> {
+fn on_dir(cwd: &Path, ignore: &IgnoreRules, path: &str) -> Result<(), M8FstoErr> {
     let mut errors = vec![];
 
     for entry in glob(path)
         .map_err(|e|M8FstoErr::InvalidSearchPattern { pattern: format!("{:?}", e) })? {
         match entry {
             Err(e) => println!("{:?}", e),
+            Ok(path) if ignore.is_ignored(&path) => {}
             Ok(path) => {
                 let try_as_file = fs::read(&path);
                 match try_as_file {
                     Err(e) => {
                         errors.push(M8FstoErr::CannotReadFile {
                             path: path.to_path_buf(),
-                            reason: format!("{:?}", e)
+                            source: Arc::new(e)
                         })
                     }
                     Ok(file_blob) => {
-                        match on_file_blob(cwd, path.as_path(), file_blob) {
+                        match on_file_blob(cwd, ignore, path.as_path(), file_blob) {
                             Ok(()) => {},
                             Err(e) => errors.push(e)
                         }
@@ -63,26 +68,20 @@ fn on_dir(cwd: &Path, path: &str) -> Result<(), M8FstoErr> {
         }
     }
 
-    if errors.len() == 0 {
-        Ok(())
-    } else if errors.len() == 1 {
-        Err(errors[0].clone())
-    } else {
-        Err(M8FstoErr::MultiErrs { inner: errors })
-    }
+    M8FstoErr::from_file_errors(errors)
 }
 
 /// Try to list sample of a given path
-pub fn ls_sample(cwd: &Path, path : &Option<String>) -> Result<(), M8FstoErr> {
+pub fn ls_sample(cwd: &Path, ignore: &IgnoreRules, path : &Option<String>) -> Result<(), M8FstoErr> {
     match path {
-        None => on_dir(cwd, "./"),
+        None => on_dir(cwd, ignore, "./"),
         Some(path) => {
             let try_as_file = fs::read(path);
             match try_as_file {
-                Err(_) => { on_dir(cwd, path) }
+                Err(_) => { on_dir(cwd, ignore, path) }
                 Ok(file_blob) => {
                     let as_path = Path::new(path);
-                    on_file_blob(cwd, as_path, file_blob)
+                    on_file_blob(cwd, ignore, as_path, file_blob)
                 }
             }
         }
@@ -1,8 +1,11 @@
-use std::{io::stdout, path::PathBuf};
+use std::{io::stdout, ops::Range, path::PathBuf, process::ExitCode};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use clap_num::maybe_hex;
-use types::{FlagBag, M8FstoErr};
+use diff_song::DiffFormat;
+use ignore_rules::IgnoreRules;
+use merge_song::MergeSide;
+use types::{ErrorReport, ExitStatus, FlagBag, M8FstoErr, Outcome};
 
 mod ls_sample;
 mod grep_sample;
@@ -12,6 +15,43 @@ mod broken_search;
 mod types;
 mod show_song;
 mod move_samples;
+mod ignore_rules;
+mod arrangement;
+mod song_file;
+mod flat_params;
+mod diff_song;
+mod merge_song;
+mod midi_export;
+mod midi_import;
+mod slots;
+mod dump_song;
+mod instrument_file;
+mod copy_chain;
+mod fx_names;
+mod compact;
+mod song_scan;
+mod lint;
+mod grep_fx;
+mod grep_instrument;
+mod grep_notes;
+mod catalog;
+mod versions;
+mod colorize;
+mod browse;
+mod eq_curve;
+mod eq_plot;
+mod html_export;
+mod mod_curves;
+
+/// Parse an instrument number, prefix with 0x to use hexadecimal notation
+fn instrument_number(s: &str) -> Result<u8, String> {
+    clap_num::maybe_hex_range(s, 0, 0x7F)
+}
+
+/// Parse a chain number, prefix with 0x to use hexadecimal notation
+fn chain_number(s: &str) -> Result<usize, String> {
+    clap_num::maybe_hex_range(s, 0, 0xFE)
+}
 
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
@@ -40,6 +80,19 @@ enum ShowTarget {
     /// Display effect settings
     Effects,
 
+    /// Print the song with chains and phrases expanded, one
+    /// line per step, in song order.
+    Arrangement {
+        /// Track to display (1 to 8), all tracks are displayed if not set
+        #[arg(short, long, value_parser=clap::value_parser!(u8).range(1..=8))]
+        track: Option<u8>,
+
+        /// Song rows to display, like `0x10..0x20`, `4..` or `..8`.
+        /// By default, up to the last used row.
+        #[arg(short, long, value_parser=arrangement::parse_row_range)]
+        rows: Option<Range<usize>>
+    },
+
     /// Print the content of a chain
     Chain {
         #[clap(value_parser=maybe_hex::<usize>)]
@@ -55,7 +108,12 @@ enum ShowTarget {
     /// Print the content of a phrase
     Instrument {
         #[clap(value_parser=maybe_hex::<usize>)]
-        id: Option<usize>
+        id: Option<usize>,
+
+        /// Draw the envelopes and LFOs of the instrument as curves
+        /// instead of printing its parameters
+        #[arg(long)]
+        curves: bool
     },
 
     /// Print the content of a table
@@ -64,7 +122,25 @@ enum ShowTarget {
         id: Option<usize>
     },
 
-    /// Print EQ information
+    /// Print the ticks of a groove
+    Groove {
+        #[clap(value_parser=maybe_hex::<usize>)]
+        id: usize
+    },
+
+    /// Print the key and note offsets of a scale
+    Scale {
+        #[clap(value_parser=maybe_hex::<usize>)]
+        id: usize
+    },
+
+    /// Print MIDI and sync settings
+    Midi,
+
+    /// Count the uses of each FX command in phrases and tables
+    FxStats,
+
+    /// Print EQ information and plot its frequency response
     Eq {
         #[clap(value_parser=maybe_hex::<usize>)]
         id: Option<usize>
@@ -76,10 +152,41 @@ struct ShowCommand {
     #[structopt(subcommand)]
     pub show_command: ShowTarget,
 
+    /// Color the song, chain, phrase and table views
+    #[arg(long, value_enum, default_value_t = colorize::ColorChoice::Auto)]
+    pub color: colorize::ColorChoice,
+
+    /// M8 theme file (`.m8t`) giving the colors, the device default
+    /// theme is used otherwise
+    #[arg(long)]
+    pub theme: Option<String>,
+
     /// File to display
     pub file: String
 }
 
+/// Options shared by the commands scanning folders for songs
+#[derive(Args)]
+struct ScanArgs {
+    /// Skip files or folders matching this gitignore-style pattern,
+    /// in addition to the rules of the `.m8fstoignore` file at the
+    /// root. Can be repeated.
+    #[arg(short = 'x', long)]
+    exclude: Vec<String>,
+
+    /// Skip the songs made with a firmware older than this version,
+    /// like `4.0`
+    #[arg(long, value_parser=ignore_rules::parse_min_version)]
+    min_version: Option<ignore_rules::MinVersion>
+}
+
+impl ScanArgs {
+    fn ignore_rules(&self, root: &std::path::Path) -> Result<IgnoreRules, M8FstoErr> {
+        IgnoreRules::load(root, &self.exclude)
+            .map(|rules| rules.with_min_version(self.min_version))
+    }
+}
+
 #[derive(Subcommand)]
 enum M8Commands {
     Show(ShowCommand),
@@ -87,7 +194,10 @@ enum M8Commands {
     /// List samples used in M8 song file
     LsSample {
         /// Optional path/folder
-        path: Option<String>
+        path: Option<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
     },
 
     /// Try to find songs that are using a given sample
@@ -97,7 +207,82 @@ enum M8Commands {
         pattern : String,
 
         /// In which folder to search
-        path : Option<String>
+        path : Option<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
+    },
+
+    /// Find the phrase and table steps using a given FX command
+    GrepFx {
+        /// FX command to search, like `DEL`, glob patterns can be
+        /// used like `FM*`
+        pattern : String,
+
+        /// Optional paths to process: directories or `.m8s` song files.
+        /// If not set, the current working directory is used.
+        paths: Vec<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
+    },
+
+    /// Find instruments matching all the given criteria
+    GrepInstrument {
+        /// Instrument kind, like `FMSynth` or `Sample`
+        #[arg(short, long)]
+        kind: Option<String>,
+
+        /// Instrument name, using glob patterns
+        #[arg(short, long, value_parser=grep_instrument::parse_name_pattern)]
+        name: Option<glob::Pattern>,
+
+        /// MIDI channel of MIDI out and external instruments
+        #[arg(short, long, value_parser=maybe_hex::<u8>)]
+        channel: Option<u8>,
+
+        /// MIDI program of MIDI out and external instruments
+        #[arg(short, long, value_parser=maybe_hex::<u8>)]
+        program: Option<u8>,
+
+        /// Shape of WavSynth and MacroSynth instruments
+        #[arg(short, long, value_parser=maybe_hex::<u8>)]
+        shape: Option<u8>,
+
+        /// Parameter predicate like `FILTER=LOWPASS`, as named by `show
+        /// instrument`, the value can use glob patterns. Can be repeated.
+        #[arg(short = 'P', long = "param", value_parser=grep_instrument::parse_param_filter)]
+        params: Vec<grep_instrument::ParamFilter>,
+
+        /// Optional paths to process: directories or `.m8s` song files.
+        /// If not set, the current working directory is used.
+        paths: Vec<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
+    },
+
+    /// Find the phrases containing a note sequence, the sequence
+    /// is not searched across the phrases of a chain.
+    GrepNotes {
+        /// Match the sequence in any key
+        #[arg(short, long)]
+        transpose_invariant: bool,
+
+        /// Skip the empty steps of the phrases, the notes don't
+        /// need to be on consecutive rows.
+        #[arg(short, long)]
+        ignore_empty: bool,
+
+        /// Notes to search, as displayed in phrases, like "C-4 D#4 G-4"
+        notes: String,
+
+        /// Optional paths to process: directories or `.m8s` song files.
+        /// If not set, the current working directory is used.
+        paths: Vec<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
     },
 
     /// Bundle a song, avoiding sample duplication
@@ -133,6 +318,305 @@ enum M8Commands {
         /// Optional paths to process: directories or `.m8s` song files.
         /// If not set, the current working directory is used.
         paths: Vec<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
+    },
+
+    /// Compare two songs, only printing the elements that differ
+    Diff {
+        /// Output format, tsv gives one line per change
+        #[arg(short, long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+
+        /// Original song
+        before: String,
+
+        /// Modified song
+        after: String
+    },
+
+    /// Three way merge of two versions of a song derived from a
+    /// common base, at the chain, phrase, instrument, table and song
+    /// row level.
+    Merge {
+        /// Side to keep for elements modified on both sides, if not
+        /// set conflicts are only reported.
+        #[arg(short, long, value_enum)]
+        prefer: Option<MergeSide>,
+
+        /// Where to write the merged song
+        #[arg(short, long)]
+        output: String,
+
+        /// Common ancestor of the two songs
+        base: String,
+
+        /// Our version of the song, used as starting point
+        ours: String,
+
+        /// Their version of the song
+        theirs: String
+    },
+
+    /// Export the 8 tracks of a song to a standard MIDI file
+    ExportMidi {
+        /// Song row where the export starts, prefix with 0x to
+        /// use hexadecimal notation.
+        #[arg(short, long, default_value_t = 0, value_parser=maybe_hex::<usize>)]
+        start_row: usize,
+
+        /// Song to export
+        song: String,
+
+        /// MIDI file to write
+        out: String
+    },
+
+    /// Plot the frequency response of EQs, to compare them. Without
+    /// EQ or instrument, the EQs used by the instruments are plotted.
+    PlotEq {
+        /// EQ to plot, prefix with 0x to use hexadecimal notation.
+        /// Can be repeated.
+        #[arg(short, long, value_parser=maybe_hex::<usize>)]
+        eq: Vec<usize>,
+
+        /// Instrument whose EQ is plotted, can be repeated
+        #[arg(short, long, value_parser=maybe_hex::<usize>)]
+        instrument: Vec<usize>,
+
+        /// Write the plot as an SVG image instead of printing it
+        #[arg(long)]
+        svg: Option<String>,
+
+        /// Write the plot as a PNG image instead of printing it
+        #[arg(long)]
+        png: Option<String>,
+
+        /// Color the curves
+        #[arg(long, value_enum, default_value_t = colorize::ColorChoice::Auto)]
+        color: colorize::ColorChoice,
+
+        /// Song or instrument file
+        file: String
+    },
+
+    /// Export a song as a self contained HTML page: song grid, chains,
+    /// phrases, instrument parameters, EQ curves and samples.
+    ExportHtml {
+        /// Song to export
+        song: String,
+
+        /// HTML file to write
+        out: String
+    },
+
+    /// Import a MIDI clip in a song, in free phrases and chains
+    /// placed on the song grid.
+    ImportMidi {
+        /// If set, only display the allocated phrases, chains and
+        /// song rows, without writing the song.
+        #[arg(short, long)]
+        dry_run : bool,
+
+        /// Song receiving the MIDI clip
+        #[arg(long)]
+        into: String,
+
+        /// Track (1 to 8) where the chains are placed
+        #[arg(short, long, value_parser=clap::value_parser!(u8).range(1..=8))]
+        track: u8,
+
+        /// Instrument playing the notes
+        #[arg(short, long, value_parser=instrument_number)]
+        instrument: u8,
+
+        /// First song row receiving the chains, by default the row
+        /// following the last chain of the track.
+        #[arg(short, long, value_parser=maybe_hex::<usize>)]
+        row: Option<usize>,
+
+        /// MIDI file to import
+        midi: String
+    },
+
+    /// Print a song or instrument file as JSON, the dump can be
+    /// edited and turned back into a file with `load`.
+    Dump {
+        /// Song or instrument file to dump
+        file: String
+    },
+
+    /// Rebuild a song or instrument file from a JSON dump made with `dump`
+    Load {
+        /// Where to write the song or instrument file
+        #[arg(short, long)]
+        output: String,
+
+        /// JSON dump of the song or instrument
+        json: String
+    },
+
+    /// Write a song instrument, with its table and EQ, as a standalone
+    /// instrument file.
+    ExtractInstrument {
+        /// Extract all the instruments of the song, `--output` being
+        /// the destination folder.
+        #[arg(short, long)]
+        all: bool,
+
+        /// Copy the sample of sampler instruments next to the
+        /// instrument file.
+        #[arg(short, long)]
+        with_sample: bool,
+
+        /// Root folder for the sample path, if not set, current
+        /// working directory is used.
+        #[arg(short, long)]
+        root: Option<String>,
+
+        /// Instrument file to write, by default named after the
+        /// instrument in the current directory.
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Song containing the instrument
+        song: String,
+
+        /// Instrument to extract, prefix with 0x to use hexadecimal notation
+        #[arg(value_parser=instrument_number, required_unless_present="all", conflicts_with="all")]
+        instrument: Option<u8>
+    },
+
+    /// Write an instrument file, with its table and EQ, in a song
+    ImportInstrument {
+        /// If set, only display the slots which would be used,
+        /// without writing the song.
+        #[arg(short, long)]
+        dry_run : bool,
+
+        /// Overwrite the slot even if it holds an instrument
+        #[arg(short, long)]
+        force : bool,
+
+        /// Root folder for the sample path, if not set, current
+        /// working directory is used.
+        #[arg(short, long)]
+        root: Option<String>,
+
+        /// Song receiving the instrument
+        #[arg(long)]
+        into: String,
+
+        /// Instrument slot to write, by default the first free one
+        #[arg(short, long, value_parser=instrument_number)]
+        slot: Option<u8>,
+
+        /// Instrument file to import
+        instrument: String
+    },
+
+    /// Copy a chain, with its phrases, instruments and tables, in free
+    /// slots of another song.
+    CopyChain {
+        /// If set, only display the slots which would be used,
+        /// without writing the destination song.
+        #[arg(short, long)]
+        dry_run : bool,
+
+        /// Song containing the chain
+        src: String,
+
+        /// Chain to copy, prefix with 0x to use hexadecimal notation
+        #[arg(value_parser=chain_number)]
+        chain: usize,
+
+        /// Song receiving the chain
+        dst: String
+    },
+
+    /// List the chains, phrases, instruments and tables which cannot
+    /// be reached from the song grid.
+    Compact {
+        /// If set, only display what would be cleared or moved,
+        /// without writing the song.
+        #[arg(short, long)]
+        dry_run : bool,
+
+        /// Clear the unused elements
+        #[arg(short, long, conflicts_with="renumber")]
+        clear: bool,
+
+        /// Clear the unused elements and move the used ones to
+        /// the lowest numbers, updating all the references.
+        #[arg(short, long)]
+        renumber: bool,
+
+        /// Song to compact
+        song: String
+    },
+
+    /// Check songs for suspicious content, like phrases using empty
+    /// instruments or missing samples. One tab separated line is
+    /// printed per finding: song, check, element and message.
+    Lint {
+        /// Root folder for the sample path, if not set, current
+        /// working directory is used.
+        #[arg(short, long)]
+        root: Option<String>,
+
+        /// Optional paths to process: directories or `.m8s` song files.
+        /// If not set, the root folder is used.
+        paths: Vec<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
+    },
+
+    /// Describe every song of a backup: name, version, tempo,
+    /// instruments, samples, chains, phrases, modification date and
+    /// broken samples.
+    Catalog {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = catalog::CatalogFormat::Text)]
+        format: catalog::CatalogFormat,
+
+        /// File to write, by default the catalog is printed
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Root folder of the backup, if not set, current working
+        /// directory is used.
+        root: Option<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
+    },
+
+    /// Group the songs of a backup by firmware version, flagging the
+    /// songs which can be read but not written back, and list the
+    /// commands available for them.
+    Versions {
+        /// Root folder of the backup, if not set, current working
+        /// directory is used.
+        root: Option<String>,
+
+        #[command(flatten)]
+        scan: ScanArgs
+    },
+
+    /// Browse a song, or the songs of a backup folder with their
+    /// samples, in a terminal interface navigating like the M8: song,
+    /// chain, phrase, instrument and table screens.
+    Browse {
+        /// M8 theme file (`.m8t`) giving the colors, the device default
+        /// theme is used otherwise
+        #[arg(long)]
+        theme: Option<String>,
+
+        /// Song file or backup folder, if not set, current working
+        /// directory is used.
+        path: Option<String>
     },
 
     /// Move a sample or sample folder and update songs referencing
@@ -157,36 +641,67 @@ enum M8Commands {
         from: String,
 
         /// Destination
-        to: String
+        to: String,
+
+        #[command(flatten)]
+        scan: ScanArgs
     }
 }
 
-fn print_errors(r : Result<(), M8FstoErr>) {
+/// Print the errors on stderr and compute the process exit status
+fn report(r : Result<Outcome, M8FstoErr>) -> ExitStatus {
     match r {
-        Ok(()) => {}
-        Err(e) => eprintln!("{}", e)
+        Ok(outcome) => outcome.into(),
+        Err(e) => {
+            eprint!("{}", ErrorReport(&e));
+            e.exit_status()
+        }
     }
 }
 
-fn main() {
+/// For commands without findings.
+fn print_errors(r : Result<(), M8FstoErr>) -> ExitStatus {
+    report(r.map(|()| Outcome::Clean))
+}
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
     let cwd = std::env::current_dir().unwrap();
 
-    match cli.command {
-        None => { println!("Please use a command") }
+    let status = match cli.command {
+        None => {
+            println!("Please use a command");
+            ExitStatus::Fatal
+        }
         Some(M8Commands::Show(showcmd)) => {
-            print_errors(show_song::show_element(showcmd, &mut stdout()));
+            print_errors(show_song::show_element(showcmd, &mut stdout()))
+        }
+        Some(M8Commands::LsSample { path, scan }) => {
+            print_errors(scan.ignore_rules(&cwd)
+                .and_then(|ignore| ls_sample::ls_sample(cwd.as_path(), &ignore, &path)))
         }
-        Some(M8Commands::LsSample { path }) => {
-            print_errors(ls_sample::ls_sample(cwd.as_path(), &path))
+        Some(M8Commands::GrepSample { pattern, path, scan }) => {
+            print_errors(scan.ignore_rules(&cwd)
+                .and_then(|ignore| grep_sample::grep_sample(cwd.as_path(), &ignore, &pattern, &path)))
         }
-        Some(M8Commands::GrepSample { pattern, path }) => {
-            print_errors(grep_sample::grep_sample(cwd.as_path(), &pattern, &path))
+        Some(M8Commands::GrepFx { pattern, paths, scan }) => {
+            report(scan.ignore_rules(&cwd)
+                .and_then(|ignore| grep_fx::grep_fx(&cwd, &ignore, &pattern, &paths)))
         }
-        Some(M8Commands::BrokenSearch { root, paths }) => {
+        Some(M8Commands::GrepInstrument { kind, name, channel, program, shape, params, paths, scan }) => {
+            let filter = grep_instrument::InstrumentFilter { kind, name, channel, program, shape, params };
+            report(scan.ignore_rules(&cwd)
+                .and_then(|ignore| grep_instrument::grep_instrument(&cwd, &ignore, &filter, &paths)))
+        }
+        Some(M8Commands::GrepNotes { transpose_invariant, ignore_empty, notes, paths, scan }) => {
+            report(scan.ignore_rules(&cwd)
+                .and_then(|ignore| grep_notes::grep_notes(&cwd, &ignore, &notes, transpose_invariant, ignore_empty, &paths)))
+        }
+        Some(M8Commands::BrokenSearch { root, paths, scan }) => {
             let root =
                 root.map_or_else(|| cwd.clone(), |e| PathBuf::from(e));
-            print_errors(broken_search::process_paths(&root, &paths))
+            report(scan.ignore_rules(&root)
+                .and_then(|ignore| broken_search::process_paths(&root, &ignore, &paths)))
         }
         Some(M8Commands::Bundle { song, root, out_folder }) => {
             let root =
@@ -203,7 +718,105 @@ fn main() {
 
             print_errors(prune_bundle::prune_bundle(flags, &song))
         },
-        Some(M8Commands::Mv { root, force, dry_run, from, to }) => {
+        Some(M8Commands::Diff { format, before, after }) => {
+            report(diff_song::diff_song_files(format, &before, &after, &mut stdout()))
+        }
+        Some(M8Commands::Merge { prefer, output, base, ours, theirs }) => {
+            report(merge_song::merge_songs(&base, &ours, &theirs, &output, prefer))
+        }
+        Some(M8Commands::ExportMidi { start_row, song, out }) => {
+            print_errors(midi_export::export_midi(&song, &out, start_row))
+        }
+        Some(M8Commands::PlotEq { eq, instrument, svg, png, color, file }) => {
+            print_errors(eq_plot::plot_eq(&file, &eq, &instrument, svg.as_deref(), png.as_deref(), color))
+        }
+        Some(M8Commands::ExportHtml { song, out }) => {
+            print_errors(html_export::export_html(&song, &out))
+        }
+        Some(M8Commands::ImportMidi { dry_run, into, track, instrument, row, midi }) => {
+            let flags = FlagBag {
+                dry_run,
+                force: false,
+                verbose: false
+            };
+
+            print_errors(midi_import::import_midi(flags, &midi, &into, track as usize - 1, instrument, row))
+        }
+        Some(M8Commands::Dump { file }) => {
+            print_errors(dump_song::dump_file(&file, &mut stdout()))
+        }
+        Some(M8Commands::Load { output, json }) => {
+            print_errors(dump_song::load_file(&json, &output))
+        }
+        Some(M8Commands::ExtractInstrument { all: _, with_sample, root, output, song, instrument }) => {
+            let root =
+                root.map_or_else(|| cwd.clone(), PathBuf::from);
+
+            print_errors(instrument_file::extract_instrument(&root, &song, instrument, output.as_deref(), with_sample))
+        }
+        Some(M8Commands::ImportInstrument { dry_run, force, root, into, slot, instrument }) => {
+            let root =
+                root.map_or_else(|| cwd.clone(), PathBuf::from);
+
+            let flags = FlagBag {
+                dry_run,
+                force,
+                verbose: false
+            };
+
+            print_errors(instrument_file::import_instrument(flags, &root, &instrument, &into, slot))
+        }
+        Some(M8Commands::CopyChain { dry_run, src, chain, dst }) => {
+            let flags = FlagBag {
+                dry_run,
+                force: false,
+                verbose: false
+            };
+
+            print_errors(copy_chain::copy_chain(flags, &src, chain, &dst))
+        }
+        Some(M8Commands::Compact { dry_run, clear, renumber, song }) => {
+            let flags = FlagBag {
+                dry_run,
+                force: false,
+                verbose: false
+            };
+
+            let mode =
+                if renumber { compact::CompactMode::Renumber }
+                else if clear { compact::CompactMode::Clear }
+                else { compact::CompactMode::List };
+
+            report(compact::compact_song(flags, &song, mode))
+        }
+        Some(M8Commands::Lint { root, paths, scan }) => {
+            let root =
+                root.map_or_else(|| cwd.clone(), PathBuf::from);
+
+            report(scan.ignore_rules(&root)
+                .and_then(|ignore| lint::lint(&root, &ignore, &paths, &mut stdout())))
+        }
+        Some(M8Commands::Catalog { format, output, root, scan }) => {
+            let root =
+                root.map_or_else(|| cwd.clone(), PathBuf::from);
+
+            print_errors(scan.ignore_rules(&root)
+                .and_then(|ignore| catalog::catalog(&root, &ignore, format, output.as_deref())))
+        }
+        Some(M8Commands::Versions { root, scan }) => {
+            let root =
+                root.map_or_else(|| cwd.clone(), PathBuf::from);
+
+            report(scan.ignore_rules(&root)
+                .and_then(|ignore| versions::versions(&root, &ignore)))
+        }
+        Some(M8Commands::Browse { theme, path }) => {
+            let path =
+                path.map_or_else(|| cwd.clone(), PathBuf::from);
+
+            print_errors(browse::browse(&path, theme.as_deref()))
+        }
+        Some(M8Commands::Mv { root, force, dry_run, from, to, scan }) => {
             let root = root
                 .map_or_else(
                     || cwd.as_path().to_path_buf(),
@@ -215,7 +828,10 @@ fn main() {
                 verbose: false
             };
 
-            print_errors(move_samples::move_samples(&root, flags, from, to));
+            print_errors(scan.ignore_rules(&root)
+                .and_then(|ignore| move_samples::move_samples(&root, flags, &ignore, from, to)))
         }
-    }
+    };
+
+    status.into()
 }
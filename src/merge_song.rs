@@ -0,0 +1,128 @@
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use clap::ValueEnum;
+use m8_file_parser::writer::Writer;
+
+use crate::{arrangement::{SONG_ROW_COUNT, TRACK_COUNT}, song_file::read_song_file, types::{M8FstoErr, Outcome}};
+
+/// Side to keep when both songs modified the same element
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MergeSide {
+    Ours,
+    Theirs
+}
+
+/// Track what happened to the merged elements
+#[derive(Default)]
+struct MergeReport {
+    /// Elements only modified on their side, taken from theirs
+    taken: Vec<(&'static str, usize)>,
+
+    /// Elements modified on both sides, taken from theirs when
+    /// preferring theirs
+    conflicts: Vec<(&'static str, usize)>
+}
+
+/// Three way merge of a list of elements, `ours` is modified in place.
+fn merge_elems<T : PartialEq + Clone>(
+    kind: &'static str,
+    base: &[T],
+    ours: &mut [T],
+    theirs: &[T],
+    prefer: Option<MergeSide>,
+    report: &mut MergeReport) {
+
+    for (i, ((b, o), t)) in base.iter().zip(ours.iter_mut()).zip(theirs.iter()).enumerate() {
+        if t == b || t == o {
+            // unchanged on their side, or same modification on both
+            continue
+        }
+
+        if o != b {
+            report.conflicts.push((kind, i));
+            if prefer == Some(MergeSide::Theirs) {
+                *o = t.clone();
+            }
+            continue
+        }
+
+        *o = t.clone();
+        report.taken.push((kind, i));
+    }
+}
+
+fn song_rows(song: &m8_file_parser::Song) -> Vec<Vec<u8>> {
+    song.song.steps
+        .chunks(TRACK_COUNT)
+        .take(SONG_ROW_COUNT)
+        .map(|r| r.to_vec())
+        .collect()
+}
+
+/// Merge two songs derived from a common base song, writing the
+/// result in `output`. Conflicting elements are reported, and the
+/// song is only written if they are all resolved with `prefer`.
+pub fn merge_songs(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    output: &str,
+    prefer: Option<MergeSide>) -> Result<Outcome, M8FstoErr> {
+
+    let (base_song, _) = read_song_file(Path::new(base))?;
+    let (mut merged, ours_blob) = read_song_file(Path::new(ours))?;
+    let (theirs_song, _) = read_song_file(Path::new(theirs))?;
+
+    if base_song.version != merged.version || theirs_song.version != merged.version {
+        return Err(M8FstoErr::IncompatibleSongs {
+            reason: format!("songs use different versions ({}, {}, {})",
+                base_song.version, merged.version, theirs_song.version)
+        })
+    }
+
+    let mut report = MergeReport::default();
+
+    let mut rows = song_rows(&merged);
+    merge_elems("SONG ROW", &song_rows(&base_song), &mut rows, &song_rows(&theirs_song), prefer, &mut report);
+    for (i, row) in rows.iter().enumerate() {
+        merged.song.steps[i * TRACK_COUNT .. (i + 1) * TRACK_COUNT].copy_from_slice(row);
+    }
+
+    merge_elems("CHAIN", &base_song.chains, &mut merged.chains, &theirs_song.chains, prefer, &mut report);
+    merge_elems("PHRASE", &base_song.phrases, &mut merged.phrases, &theirs_song.phrases, prefer, &mut report);
+    merge_elems("INSTRUMENT", &base_song.instruments, &mut merged.instruments, &theirs_song.instruments, prefer, &mut report);
+    merge_elems("TABLE", &base_song.tables, &mut merged.tables, &theirs_song.tables, prefer, &mut report);
+
+    for (kind, id) in report.taken.iter() {
+        println!("Taking theirs {} {:02X}", kind, id);
+    }
+
+    for (kind, id) in report.conflicts.iter() {
+        match prefer {
+            None => println!("CONFLICT {} {:02X}", kind, id),
+            Some(MergeSide::Ours) => println!("CONFLICT {} {:02X}, keeping ours", kind, id),
+            Some(MergeSide::Theirs) => println!("CONFLICT {} {:02X}, keeping theirs", kind, id)
+        }
+    }
+
+    if prefer.is_none() && !report.conflicts.is_empty() {
+        println!("{} conflicts, use --prefer to resolve them, nothing written", report.conflicts.len());
+        return Ok(Outcome::Findings)
+    }
+
+    let mut writer = Writer::new(ours_blob);
+    merged.write(&mut writer)
+        .map_err(|reason|
+            M8FstoErr::SongSerializationError {
+                destination: output.to_string(),
+                reason
+            })?;
+
+    std::fs::write(output, writer.finish())
+        .map_err(|e| M8FstoErr::FileWriteError {
+            path: PathBuf::from(output),
+            source: Arc::new(e)
+        })?;
+
+    Ok(Outcome::Clean)
+}
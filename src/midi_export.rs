@@ -0,0 +1,140 @@
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::{Instrument, Song};
+use midly::{num::{u15, u24, u28, u4, u7}, Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+
+use crate::{arrangement::{played_rows, track_steps, EMPTY, TRACK_COUNT}, song_file::read_song_file, types::M8FstoErr};
+
+/// MIDI ticks per quarter note, matching the M8 resolution where the
+/// default groove uses 6 ticks per step.
+pub const TICKS_PER_BEAT : u16 = 24;
+
+/// Velocity used before the first velocity of a track
+const DEFAULT_VELOCITY : u8 = 0x7F;
+
+/// M8 note values from this one are note off
+pub const NOTE_OFF : u8 = 0x80;
+
+/// Ticks of each step in the first groove of the song
+fn groove_ticks(song: &Song) -> Vec<u32> {
+    let ticks : Vec<u32> = song.grooves
+        .first()
+        .map(|g| g.steps
+            .iter()
+            .take_while(|t| **t != EMPTY)
+            .filter(|t| **t > 0)
+            .map(|t| *t as u32)
+            .collect())
+        .unwrap_or_default();
+
+    if ticks.is_empty() { vec![6] } else { ticks }
+}
+
+/// MIDI channel and program of an M8 instrument. MIDI out and external
+/// instruments use their configured channel, other instruments use the
+/// track channel and their instrument number as program.
+fn instrument_channel(song: &Song, track: usize, instr: usize) -> (u8, Option<u8>) {
+    let program = |p: u8| if p == EMPTY { None } else { Some(p.min(0x7F)) };
+
+    match song.instruments.get(instr) {
+        Some(Instrument::MIDIOut(midi)) => (midi.channel.min(15), program(midi.program_change)),
+        Some(Instrument::External(ext)) => (ext.channel.min(15), program(ext.program)),
+        _ => (track as u8, Some(instr as u8 & 0x7F))
+    }
+}
+
+fn to_track_events(events: Vec<(u32, TrackEventKind<'_>)>) -> Vec<TrackEvent<'_>> {
+    let mut previous = 0;
+    events
+        .into_iter()
+        .map(|(tick, kind)| {
+            let delta = tick - previous;
+            previous = tick;
+            TrackEvent { delta: u28::new(delta), kind }
+        })
+        .collect()
+}
+
+/// Render the notes played by an M8 track, starting at a given song row
+fn render_track<'a>(song: &Song, track: usize, start_row: usize, name: &'a str) -> Vec<TrackEvent<'a>> {
+    let groove = groove_ticks(song);
+    let mut events = vec![(0, TrackEventKind::Meta(MetaMessage::TrackName(name.as_bytes())))];
+
+    let mut tick = 0;
+    let mut velocity = DEFAULT_VELOCITY;
+    let mut instrument = None;
+    let mut program_sent = None;
+    let mut playing : Option<(u4, u7)> = None;
+
+    let rows = played_rows(song, track, start_row);
+    for (i, arranged) in track_steps(song, track, rows).iter().enumerate() {
+        let step = arranged.step;
+        let note = arranged.note();
+
+        if step.velocity != EMPTY { velocity = step.velocity.min(0x7F) }
+        if step.instrument != EMPTY { instrument = Some(step.instrument as usize) }
+
+        if note.0 != EMPTY {
+            if let Some((channel, key)) = playing.take() {
+                let message = MidiMessage::NoteOff { key, vel: u7::new(0) };
+                events.push((tick, TrackEventKind::Midi { channel, message }));
+            }
+
+            if let (true, Some(instr)) = (note.0 < NOTE_OFF, instrument) {
+                let (channel, program) = instrument_channel(song, track, instr);
+                let channel = u4::new(channel);
+
+                if let Some(program) = program {
+                    if program_sent != Some((channel, program)) {
+                        let message = MidiMessage::ProgramChange { program: u7::new(program) };
+                        events.push((tick, TrackEventKind::Midi { channel, message }));
+                        program_sent = Some((channel, program));
+                    }
+                }
+
+                let key = u7::new(note.0);
+                let message = MidiMessage::NoteOn { key, vel: u7::new(velocity) };
+                events.push((tick, TrackEventKind::Midi { channel, message }));
+                playing = Some((channel, key));
+            }
+        }
+
+        tick += groove[i % groove.len()];
+    }
+
+    if let Some((channel, key)) = playing {
+        let message = MidiMessage::NoteOff { key, vel: u7::new(0) };
+        events.push((tick, TrackEventKind::Midi { channel, message }));
+    }
+
+    events.push((tick, TrackEventKind::Meta(MetaMessage::EndOfTrack)));
+    to_track_events(events)
+}
+
+/// Export the 8 tracks of a song to a standard MIDI file, the first
+/// MIDI track holding the song name and tempo.
+pub fn export_midi(song_path: &str, out_path: &str, start_row: usize) -> Result<(), M8FstoErr> {
+    let (song, _) = read_song_file(Path::new(song_path))?;
+
+    let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(TICKS_PER_BEAT)));
+    let mut smf = Smf::new(header);
+
+    let tempo = if song.tempo > 0.0 { song.tempo } else { 120.0 };
+    let micro_per_beat = (60_000_000.0 / tempo) as u32;
+    smf.tracks.push(to_track_events(vec![
+        (0, TrackEventKind::Meta(MetaMessage::TrackName(song.name.as_bytes()))),
+        (0, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(micro_per_beat)))),
+        (0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+    ]));
+
+    let names : Vec<String> = (0 .. TRACK_COUNT).map(|t| format!("TRACK {}", t + 1)).collect();
+    for (track, name) in names.iter().enumerate() {
+        smf.tracks.push(render_track(&song, track, start_row, name));
+    }
+
+    smf.save(out_path)
+        .map_err(|e| M8FstoErr::FileWriteError {
+            path: PathBuf::from(out_path),
+            source: Arc::new(e)
+        })
+}
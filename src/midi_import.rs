@@ -0,0 +1,171 @@
+use std::{fs, path::{Path, PathBuf}, sync::Arc};
+
+use m8_file_parser::{writer::Writer, Note};
+use midly::{MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::{arrangement::{chain_at, SONG_ROW_COUNT, TRACK_COUNT}, midi_export::NOTE_OFF, slots::{free_chains, free_phrases, take_slots}, song_file::read_song_file, types::{FlagBag, M8FstoErr}};
+
+/// Number of steps in a phrase, and of phrases in a chain
+const STEPS : usize = 16;
+
+/// A note of the MIDI clip, quantized to M8 steps (16th notes)
+struct ClipNote {
+    step: usize,
+    end_step: usize,
+    key: u8,
+    velocity: u8
+}
+
+/// Read all the notes of a MIDI file, quantized to 16th notes. Only
+/// one note is kept per step, the M8 tracks being monophonic.
+fn read_clip(path: &Path) -> Result<Vec<ClipNote>, M8FstoErr> {
+    let bytes = fs::read(path)
+        .map_err(|e| M8FstoErr::CannotReadFile { path: path.to_path_buf(), source: Arc::new(e) })?;
+
+    let smf = Smf::parse(&bytes)
+        .map_err(|e| M8FstoErr::UnparseableMidiFile { path: path.to_path_buf(), source: e })?;
+
+    let ticks_per_beat = match smf.header.timing {
+        Timing::Metrical(t) => t.as_int() as u32,
+        Timing::Timecode(_, _) => {
+            return Err(M8FstoErr::UnsupportedMidiFile {
+                path: path.to_path_buf(),
+                reason: "timecode based timing".into()
+            })
+        }
+    };
+
+    let step_ticks = (ticks_per_beat / 4).max(1);
+    let quantize = |tick: u32| ((tick + step_ticks / 2) / step_ticks) as usize;
+
+    let mut notes = vec![];
+    for track in smf.tracks.iter() {
+        let mut tick = 0;
+        let mut open : Vec<(u8, u8, u32, u8)> = vec![];
+        let mut close = |open: &mut Vec<(u8, u8, u32, u8)>, channel: u8, key: u8, end: u32| {
+            if let Some(i) = open.iter().position(|(c, k, _, _)| *c == channel && *k == key) {
+                let (_, _, start, velocity) = open.remove(i);
+                let step = quantize(start);
+                notes.push(ClipNote { step, end_step: quantize(end).max(step + 1), key, velocity });
+            }
+        };
+
+        for event in track.iter() {
+            tick += event.delta.as_int();
+
+            if let TrackEventKind::Midi { channel, message } = event.kind {
+                let channel = channel.as_int();
+                match message {
+                    MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                        close(&mut open, channel, key.as_int(), tick);
+                        open.push((channel, key.as_int(), tick, vel.as_int()));
+                    }
+                    MidiMessage::NoteOn { key, vel: _ } | MidiMessage::NoteOff { key, vel: _ } =>
+                        close(&mut open, channel, key.as_int(), tick),
+                    _ => {}
+                }
+            }
+        }
+
+        while let Some((channel, key, _, _)) = open.first().copied() {
+            close(&mut open, channel, key, tick);
+        }
+    }
+
+    notes.sort_by(|a, b| a.step.cmp(&b.step).then(b.velocity.cmp(&a.velocity)));
+    notes.dedup_by_key(|n| n.step);
+    Ok(notes)
+}
+
+/// Import a MIDI clip in a song, allocating free phrases and chains, and
+/// placing the chains on the song grid of a track.
+pub fn import_midi(
+    flags: FlagBag,
+    midi_path: &str,
+    song_path: &str,
+    track: usize,
+    instrument: u8,
+    row: Option<usize>) -> Result<(), M8FstoErr> {
+
+    let notes = read_clip(Path::new(midi_path))?;
+    let (mut song, song_blob) = read_song_file(Path::new(song_path))?;
+
+    let step_count = notes
+        .iter()
+        .map(|n| n.end_step)
+        .max()
+        .unwrap_or(0)
+        .div_ceil(STEPS)
+        .max(1) * STEPS;
+
+    let phrase_count = step_count / STEPS;
+    let chain_count = phrase_count.div_ceil(STEPS);
+
+    let phrases = take_slots(&free_phrases(&song), phrase_count, "phrase")?;
+    let chains = take_slots(&free_chains(&song), chain_count, "chain")?;
+
+    let start_row = row.unwrap_or_else(||
+        (0 .. SONG_ROW_COUNT)
+            .rev()
+            .find(|r| chain_at(&song, *r, track).is_some())
+            .map_or(0, |r| r + 1));
+
+    for r in start_row .. start_row + chain_count {
+        if r >= SONG_ROW_COUNT {
+            return Err(M8FstoErr::NoFreeSlot {
+                kind: "song row",
+                needed: chain_count,
+                available: SONG_ROW_COUNT.saturating_sub(start_row)
+            })
+        }
+
+        if chain_at(&song, r, track).is_some() {
+            return Err(M8FstoErr::SlotNotEmpty { kind: "song row", id: r })
+        }
+    }
+
+    for (i, note) in notes.iter().enumerate() {
+        let step = &mut song.phrases[phrases[note.step / STEPS]].steps[note.step % STEPS];
+        step.note = Note(note.key);
+        step.velocity = note.velocity;
+        step.instrument = instrument;
+
+        // Stop the note if there is a gap before the next one
+        let next_step = notes.get(i + 1).map_or(step_count, |n| n.step);
+        if note.end_step < next_step && note.end_step < step_count {
+            let off = &mut song.phrases[phrases[note.end_step / STEPS]].steps[note.end_step % STEPS];
+            off.note = Note(NOTE_OFF);
+        }
+    }
+
+    for (i, chain) in chains.iter().enumerate() {
+        let chain_phrases = phrases.iter().skip(i * STEPS).take(STEPS);
+        for (chain_step, phrase) in song.chains[*chain].steps.iter_mut().zip(chain_phrases) {
+            chain_step.phrase = *phrase as u8;
+            chain_step.transpose = 0;
+        }
+
+        song.song.steps[(start_row + i) * TRACK_COUNT + track] = *chain as u8;
+    }
+
+    let hex_list = |ids: &[usize]| ids.iter().map(|i| format!("{:02X}", i)).collect::<Vec<_>>().join(" ");
+    println!("{} notes imported", notes.len());
+    println!(" * phrases   : {}", hex_list(&phrases));
+    println!(" * chains    : {}", hex_list(&chains));
+    println!(" * song rows : {:02X} to {:02X} on track {}", start_row, start_row + chain_count - 1, track + 1);
+
+    if flags.dry_run {
+        return Ok(())
+    }
+
+    let mut writer = Writer::new(song_blob);
+    song.write(&mut writer)
+        .map_err(|reason|
+            M8FstoErr::SongSerializationError {
+                destination: song_path.to_string(),
+                reason
+            })?;
+
+    fs::write(song_path, writer.finish())
+        .map_err(|e| M8FstoErr::FileWriteError { path: PathBuf::from(song_path), source: Arc::new(e) })
+}
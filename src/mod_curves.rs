@@ -0,0 +1,349 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use m8_file_parser::{param_gatherer::{Describable, ParameterGatherer}, Instrument, LfoShape, Mod, Version};
+
+/// Number of columns of a curve
+const WIDTH : usize = 48;
+
+/// Number of rows of a curve, odd to get a zero line for the LFOs
+const HEIGHT : usize = 9;
+
+/// Length given to the sustain stage of an ADSR, which only
+/// ends with the note
+const SUSTAIN_LENGTH : f64 = 64.0;
+
+/// Cycles of a free running LFO drawn in a curve, the LFOs
+/// triggered once per note being drawn over a single cycle
+const LFO_CYCLES : f64 = 2.0;
+
+/// Steps of a random LFO per cycle
+const RANDOM_STEPS : f64 = 8.0;
+
+/// Modulators of an instrument, MIDI out instruments having none
+fn synth_mods(instr: &Instrument) -> Option<&[Mod; 4]> {
+    match instr {
+        Instrument::WavSynth(i) => Some(&i.synth_params.mods),
+        Instrument::MacroSynth(i) => Some(&i.synth_params.mods),
+        Instrument::Sampler(i) => Some(&i.synth_params.mods),
+        Instrument::FMSynth(i) => Some(&i.synth_params.mods),
+        Instrument::HyperSynth(i) => Some(&i.synth_params.mods),
+        Instrument::External(i) => Some(&i.synth_params.mods),
+        Instrument::MIDIOut(_) | Instrument::None => None
+    }
+}
+
+/// Destination of a modulator, as stored in the instrument
+fn mod_dest(modulator: &Mod) -> u8 {
+    match modulator {
+        Mod::AHDEnv(env) => env.dest,
+        Mod::ADSREnv(env) => env.dest,
+        Mod::DrumEnv(env) => env.dest,
+        Mod::TrigEnv(env) => env.dest,
+        Mod::TrackingEnv(env) => env.dest,
+        Mod::LFO(lfo) => lfo.dest
+    }
+}
+
+/// Parameter gatherer only keeping the names the parser gives to the
+/// modulator destinations, which depend on the instrument kind.
+struct DestNames {
+    names: HashMap<u8, String>
+}
+
+impl DestNames {
+    fn of(instr: &Instrument, ver: Version) -> HashMap<u8, String> {
+        instr.describe(DestNames { names: HashMap::new() }, ver).names
+    }
+}
+
+impl ParameterGatherer for DestNames {
+    fn hex(self, _name: &str, _val: u8) -> Self { self }
+    fn bool(self, _name: &str, _val: bool) -> Self { self }
+    fn float(self, _name: &str, _val: f64) -> Self { self }
+    fn str(self, _name: &str, _val: &str) -> Self { self }
+
+    fn enumeration(mut self, name: &str, hex: u8, val: &str) -> Self {
+        if name.eq_ignore_ascii_case("DEST") {
+            self.names.insert(hex, val.to_string());
+        }
+        self
+    }
+
+    fn nest_f<F>(self, _name: &str, f: F) -> Self
+        where F : FnOnce (Self) -> Self, Self : Sized {
+        f(self)
+    }
+}
+
+/// A stage of an envelope, going linearly to `level` in `length`
+struct Stage {
+    name: char,
+    length: f64,
+    level: f64
+}
+
+impl Stage {
+    /// Stage of an hex duration, zero still getting a short ramp
+    fn of(name: char, length: u8, level: f64) -> Stage {
+        Stage { name, length: length as f64 + 1.0, level }
+    }
+}
+
+/// Envelope sampled over `WIDTH` columns, starting from zero, with the
+/// column where each stage starts
+fn envelope(stages: &[Stage]) -> (Vec<f64>, Vec<(usize, char)>) {
+    let total : f64 = stages.iter().map(|s| s.length).sum();
+    let time = |x: usize| total * x as f64 / (WIDTH - 1) as f64;
+
+    let mut marks = vec![];
+    let mut start = 0.0;
+    for stage in stages {
+        marks.push(((start / total * (WIDTH - 1) as f64).round() as usize, stage.name));
+        start += stage.length;
+    }
+
+    let values = (0 .. WIDTH)
+        .map(|x| {
+            let t = time(x);
+            let mut start = 0.0;
+            let mut from = 0.0;
+            for stage in stages {
+                if t <= start + stage.length {
+                    return from + (stage.level - from) * (t - start) / stage.length
+                }
+                start += stage.length;
+                from = stage.level;
+            }
+            from
+        })
+        .collect();
+
+    (values, marks)
+}
+
+/// Small deterministic generator for the random LFO shapes
+fn noise(step: usize) -> f64 {
+    let mut x = (step as u32).wrapping_mul(0x9E37_79B9) ^ 0x5BD1_E995;
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x2C1B_3C6D);
+    x ^= x >> 12;
+    (x % 2001) as f64 / 1000.0 - 1.0
+}
+
+/// Waveform of an LFO cycle
+#[derive(Clone, Copy)]
+enum Wave {
+    Triangle,
+    Sine,
+    RampDown,
+    RampUp,
+    ExpDown,
+    ExpUp,
+    SquareDown,
+    SquareUp,
+    Random,
+    Drunk
+}
+
+/// Waveform of an LFO shape, and whether it runs once per note
+/// instead of running freely.
+fn lfo_wave(shape: LfoShape) -> (Wave, bool) {
+    match shape {
+        LfoShape::TRI => (Wave::Triangle, false),
+        LfoShape::SIN => (Wave::Sine, false),
+        LfoShape::RAMP_DOWN => (Wave::RampDown, false),
+        LfoShape::RAMP_UP => (Wave::RampUp, false),
+        LfoShape::EXP_DN => (Wave::ExpDown, false),
+        LfoShape::EXP_UP => (Wave::ExpUp, false),
+        LfoShape::SQR_DN => (Wave::SquareDown, false),
+        LfoShape::SQR_UP => (Wave::SquareUp, false),
+        LfoShape::RANDOM => (Wave::Random, false),
+        LfoShape::DRUNK => (Wave::Drunk, false),
+        LfoShape::TRI_T => (Wave::Triangle, true),
+        LfoShape::SIN_T => (Wave::Sine, true),
+        LfoShape::RAMPD_T => (Wave::RampDown, true),
+        LfoShape::RAMPU_T => (Wave::RampUp, true),
+        LfoShape::EXPD_T => (Wave::ExpDown, true),
+        LfoShape::EXPU_T => (Wave::ExpUp, true),
+        LfoShape::SQ_D_T => (Wave::SquareDown, true),
+        LfoShape::SQ_U_T => (Wave::SquareUp, true),
+        LfoShape::RAND_T => (Wave::Random, true),
+        LfoShape::DRNK_T => (Wave::Drunk, true)
+    }
+}
+
+/// Value of an LFO waveform at a phase counted in cycles, between -1 and 1
+fn lfo_value(wave: Wave, phase: f64) -> f64 {
+    let p = phase.fract();
+    match wave {
+        Wave::Triangle =>
+            if p < 0.25 { 4.0 * p } else if p < 0.75 { 2.0 - 4.0 * p } else { 4.0 * p - 4.0 },
+        Wave::Sine => (2.0 * PI * p).sin(),
+        Wave::RampDown => 1.0 - 2.0 * p,
+        Wave::RampUp => 2.0 * p - 1.0,
+        Wave::ExpDown => 2.0 * (1.0 - p).powi(3) - 1.0,
+        Wave::ExpUp => 2.0 * p.powi(3) - 1.0,
+        Wave::SquareDown => if p < 0.5 { 1.0 } else { -1.0 },
+        Wave::SquareUp => if p < 0.5 { -1.0 } else { 1.0 },
+        Wave::Random => noise((phase * RANDOM_STEPS) as usize),
+        Wave::Drunk => {
+            let steps = (phase * RANDOM_STEPS) as usize;
+            ((0 ..= steps).map(|s| noise(s) * 0.5).sum::<f64>()).clamp(-1.0, 1.0)
+        }
+    }
+}
+
+/// Rows of the curve, top first. Bipolar curves go from -1 to 1 with
+/// a zero line, the others from 0 to 1.
+fn plot(values: &[f64], bipolar: bool) -> Vec<String> {
+    let level = |v: f64| {
+        let v = if bipolar { (v + 1.0) / 2.0 } else { v };
+        (v.clamp(0.0, 1.0) * (HEIGHT - 1) as f64).round() as usize
+    };
+
+    let mut grid = vec![vec![' '; values.len()]; HEIGHT];
+    if bipolar {
+        grid[HEIGHT / 2].fill('-');
+    }
+
+    let mut previous : Option<usize> = None;
+    for (x, v) in values.iter().enumerate() {
+        let y = level(*v);
+        if let Some(p) = previous {
+            for r in (p.min(y) + 1) .. p.max(y) {
+                grid[HEIGHT - 1 - r][x] = '|';
+            }
+        }
+        grid[HEIGHT - 1 - y][x] = '*';
+        previous = Some(y);
+    }
+
+    grid.into_iter()
+        .enumerate()
+        .map(|(row, cells)| {
+            let axis = match (row, bipolar) {
+                (0, true) => "+FF",
+                (0, false) => " FF",
+                (r, true) if r == HEIGHT / 2 => " 00",
+                (r, true) if r == HEIGHT - 1 => "-FF",
+                (r, false) if r == HEIGHT - 1 => " 00",
+                _ => "   "
+            };
+            format!("{} |{}", axis, cells.into_iter().collect::<String>().trim_end())
+        })
+        .collect()
+}
+
+/// X axis, with the stage names under the columns they start at
+fn x_axis(marks: &[(usize, char)]) -> Vec<String> {
+    let mut names = vec![' '; WIDTH];
+    for (x, name) in marks {
+        names[(*x).min(WIDTH - 1)] = *name;
+    }
+
+    let mut lines = vec![format!("    +{}", "-".repeat(WIDTH))];
+    if !marks.is_empty() {
+        lines.push(format!("     {}", names.into_iter().collect::<String>().trim_end()));
+    }
+    lines
+}
+
+/// Title, curve and axis of a modulator
+fn mod_curve(index: usize, modulator: &Mod, dest: &str) -> Vec<String> {
+    let scale = |amount: u8, values: Vec<f64>| -> Vec<f64> {
+        values.into_iter().map(|v| v * amount as f64 / 255.0).collect()
+    };
+
+    let (title, values, marks, bipolar) = match modulator {
+        Mod::AHDEnv(env) => {
+            let (values, marks) = envelope(&[
+                Stage::of('A', env.attack, 1.0),
+                Stage::of('H', env.hold, 1.0),
+                Stage::of('D', env.decay, 0.0)
+            ]);
+            (format!("AHD ENV  amount {:02X}  attack {:02X} hold {:02X} decay {:02X}",
+                     env.amount, env.attack, env.hold, env.decay),
+             scale(env.amount, values), marks, false)
+        }
+        Mod::ADSREnv(env) => {
+            let sustain = env.sustain as f64 / 255.0;
+            let (values, marks) = envelope(&[
+                Stage::of('A', env.attack, 1.0),
+                Stage::of('D', env.decay, sustain),
+                Stage { name: 'S', length: SUSTAIN_LENGTH, level: sustain },
+                Stage::of('R', env.release, 0.0)
+            ]);
+            (format!("ADSR ENV  amount {:02X}  attack {:02X} decay {:02X} sustain {:02X} release {:02X}",
+                     env.amount, env.attack, env.decay, env.sustain, env.release),
+             scale(env.amount, values), marks, false)
+        }
+        Mod::DrumEnv(env) => {
+            // drawn as a short peak falling to the body level, then decaying
+            let (values, marks) = envelope(&[
+                Stage { name: 'P', length: 1.0, level: 1.0 },
+                Stage::of('B', env.peak, env.body as f64 / 255.0),
+                Stage::of('D', env.decay, 0.0)
+            ]);
+            (format!("DRUM ENV  amount {:02X}  peak {:02X} body {:02X} decay {:02X}",
+                     env.amount, env.peak, env.body, env.decay),
+             scale(env.amount, values), marks, false)
+        }
+        Mod::TrigEnv(env) => {
+            let (values, marks) = envelope(&[
+                Stage::of('A', env.attack, 1.0),
+                Stage::of('H', env.hold, 1.0),
+                Stage::of('D', env.decay, 0.0)
+            ]);
+            (format!("TRIG ENV  amount {:02X}  attack {:02X} hold {:02X} decay {:02X} src {:02X}",
+                     env.amount, env.attack, env.hold, env.decay, env.src),
+             scale(env.amount, values), marks, false)
+        }
+        Mod::TrackingEnv(env) => {
+            // not a time curve, the x axis is the source value
+            let (low, high) = (env.lval as f64 / 255.0, env.hval as f64 / 255.0);
+            let values = (0 .. WIDTH)
+                .map(|x| low + (high - low) * x as f64 / (WIDTH - 1) as f64)
+                .collect();
+            (format!("TRACKING ENV  amount {:02X}  src {:02X} low {:02X} high {:02X}",
+                     env.amount, env.src, env.lval, env.hval),
+             scale(env.amount, values), vec![(0, 'L'), (WIDTH - 1, 'H')], false)
+        }
+        Mod::LFO(lfo) => {
+            let (wave, once) = lfo_wave(lfo.shape);
+            let cycles = if once { 1.0 } else { LFO_CYCLES };
+            let values = (0 .. WIDTH)
+                .map(|x| lfo_value(wave, cycles * x as f64 / WIDTH as f64))
+                .collect();
+            (format!("LFO {:?}  amount {:02X}  freq {:02X} trigger {:02X}",
+                     lfo.shape, lfo.amount, lfo.freq, lfo.trigger_mode),
+             scale(lfo.amount, values), vec![], true)
+        }
+    };
+
+    let mut lines = vec![format!("MOD {} -> {}  {}", index + 1, dest, title)];
+    lines.extend(plot(&values, bipolar));
+    lines.extend(x_axis(&marks));
+    lines
+}
+
+/// Modulators of an instrument drawn as ASCII curves, time on the
+/// x axis and amount on the y axis.
+pub fn mod_curves(instr: &Instrument, ver: Version) -> String {
+    let Some(mods) = synth_mods(instr) else {
+        return "No modulators for this instrument\n".to_string()
+    };
+
+    let names = DestNames::of(instr, ver);
+    let mut out = String::new();
+    for (i, modulator) in mods.iter().enumerate() {
+        let dest = mod_dest(modulator);
+        let dest = names.get(&dest).cloned().unwrap_or_else(|| format!("{:02X}", dest));
+        for line in mod_curve(i, modulator, &dest) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+
+    out
+}
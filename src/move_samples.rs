@@ -2,9 +2,11 @@ use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::path::Component;
+use std::sync::Arc;
 use glob::glob;
 use m8_file_parser::{reader::*, Instrument};
 
+use crate::ignore_rules::IgnoreRules;
 use crate::types::combine;
 use crate::types::FlagBag;
 use crate::types::M8FstoErr;
@@ -67,15 +69,43 @@ struct SwappedFile {
     touched: Vec<SwappedInstruments>
 }
 
-fn on_file_blob(flags: &FlagBag, swap: &Swap, path: &Path, data: Vec<u8>) -> Result<Option<SwappedFile>, M8FstoErr> {
+/// Skipped songs keep their sample paths, warn when they use a moved
+/// sample as they will point to a missing file.
+fn warn_skipped(song: &m8_file_parser::Song, swap: &Swap, path: &Path, reason: &str) {
+    let uses_moved = song.instruments.iter().any(|instr| match instr {
+        Instrument::Sampler(sampler) => swap.try_swap(&sampler.sample_path).is_some(),
+        _ => false
+    });
+
+    if uses_moved {
+        eprintln!("Warning: {:?} uses moved samples but is skipped ({}), it keeps the old paths", path, reason);
+    }
+}
+
+/// Warn about a song skipped by the ignore rules, songs which can't be
+/// read being left aside like the other ignored files.
+fn warn_ignored(swap: &Swap, path: &Path) {
+    let Ok(data) = fs::read(path) else { return };
+    let mut reader = Reader::new(data);
+    if let Ok(song) = m8_file_parser::Song::read_from_reader(&mut reader) {
+        warn_skipped(&song, swap, path, "ignored");
+    }
+}
+
+fn on_file_blob(flags: &FlagBag, ignore: &IgnoreRules, swap: &Swap, path: &Path, data: Vec<u8>) -> Result<Option<SwappedFile>, M8FstoErr> {
     let mut reader = Reader::new(data.clone());
     let mut touched = vec![];
     let mut song = m8_file_parser::Song::read_from_reader(&mut reader)
         .map_err(|e| M8FstoErr::UnparseableM8File {
             path: path.to_path_buf(),
-            reason: format!("{:?}", e)
+            source: Arc::new(e)
         })?;
 
+    if ignore.is_version_ignored(&song.version) {
+        warn_skipped(&song, swap, path, "older than --min-version");
+        return Ok(None)
+    }
+
     for (instrument, instr) in song.instruments.iter_mut().enumerate() {
         match instr {
             Instrument::Sampler(sampler) => {
@@ -98,6 +128,15 @@ fn on_file_blob(flags: &FlagBag, swap: &Swap, path: &Path, data: Vec<u8>) -> Res
 
     if touched.len() == 0 { return Ok(None);}
 
+    // The parser can't write back songs older than 4.0, and their layout
+    // is not known well enough to patch the sample paths in place.
+    if song.version.major < 4 {
+        return Err(M8FstoErr::SongSerializationError {
+            reason: format!("songs made with firmware {} can't be rewritten, only 4.0 and above", song.version),
+            destination: format!("{:?}", path)
+        })
+    }
+
     if flags.dry_run {
         return Ok(Some(SwappedFile {
             file_data: Vec::new(),
@@ -121,7 +160,7 @@ fn on_file_blob(flags: &FlagBag, swap: &Swap, path: &Path, data: Vec<u8>) -> Res
     }))
 }
 
-fn on_dir(flags: &FlagBag, cwd: &Path, swap: &Swap) -> Result<(), M8FstoErr> {
+fn on_dir(flags: &FlagBag, cwd: &Path, ignore: &IgnoreRules, swap: &Swap) -> Result<(), M8FstoErr> {
     let mut errors = None;
     let mut matched_not_serializable = vec![];
     let mut to_write= vec![];
@@ -132,20 +171,23 @@ fn on_dir(flags: &FlagBag, cwd: &Path, swap: &Swap) -> Result<(), M8FstoErr> {
     for entry in search_pattern {
         match entry {
             Err(e) => println!("{:?}", e),
+            Ok(path) if ignore.is_ignored(&path) => warn_ignored(swap, &path),
             Ok(path) => {
                 let try_as_file = fs::read(&path);
                 match try_as_file {
                     Err(e) => {
                         errors = combine(errors, M8FstoErr::CannotReadFile {
                             path: path.to_path_buf(),
-                            reason: format!("{:?}", e)
+                            source: Arc::new(e)
                         })
                     }
                     Ok(file_blob) => {
-                        match on_file_blob(flags, swap, &path, file_blob) {
+                        match on_file_blob(flags, ignore, swap, &path, file_blob) {
                             Ok(None) => {}
-                            Err(M8FstoErr::SongSerializationError { reason: _, destination}) =>
-                                matched_not_serializable.push(destination),
+                            Err(m8err @ M8FstoErr::SongSerializationError { .. }) => {
+                                matched_not_serializable.push(path.clone());
+                                errors = combine(errors, m8err)
+                            }
                             Err(m8err) =>
                                 errors = combine(errors, m8err),
                             Ok(Some(swapped)) => {
@@ -168,11 +210,11 @@ fn on_dir(flags: &FlagBag, cwd: &Path, swap: &Swap) -> Result<(), M8FstoErr> {
         for (path, data) in to_write {
             match fs::write(&path, data) {
                 Ok(()) => {}
-                Err(_) => {
-                    errors = combine(errors, 
-                        M8FstoErr::SongSerializationError {
-                            reason: "Error while writing file".into(),
-                            destination: format!("{:?}", path)
+                Err(e) => {
+                    errors = combine(errors,
+                        M8FstoErr::FileWriteError {
+                            path: path.clone(),
+                            source: Arc::new(e)
                         });
                 }
             }
@@ -217,6 +259,7 @@ pub fn normalize_path(path: &Path) -> PathBuf {
 pub fn move_samples(
     cwd: &Path,
     flags: FlagBag,
+    ignore: &IgnoreRules,
     from: String,
     to: String) -> Result<(), M8FstoErr> {
 
@@ -277,20 +320,19 @@ pub fn move_samples(
                 to: format!("/{}", rel_to),
             }
         } else {
-            return Err(M8FstoErr::CannotReadFile { path: from_path, reason: String::from("Neither file nor directory")})
+            return Err(M8FstoErr::InvalidPath { reason: format!("{:?} is neither file nor directory", from_path) })
         };
 
-    match on_dir(&flags, &cwd, &move_order) {
+    match on_dir(&flags, &cwd, ignore, &move_order) {
         Ok(()) => {
             std::fs::rename(&from_canon, to_canon)
-                .map_err(|_| M8FstoErr::RenameFailure { path: format!("{:?}", from_canon) })
+                .map_err(|e| M8FstoErr::RenameFailure { path: from_canon.clone(), source: Arc::new(e) })
         }
         Err(errs) if flags.force => {
             match std::fs::rename(&from_canon, to_canon) {
                 Ok(()) => Err(errs),
-                Err(_) => {
-                    let path = format!("{:?}", from_canon);
-                    Err(errs.combine(M8FstoErr::RenameFailure { path }))
+                Err(e) => {
+                    Err(errs.combine(M8FstoErr::RenameFailure { path: from_canon.clone(), source: Arc::new(e) }))
                 }
             }
         }
@@ -1,4 +1,4 @@
-use std::{collections::HashSet, fs, path::{Path, PathBuf}};
+use std::{collections::HashSet, fs, path::{Path, PathBuf}, sync::Arc};
 use m8_file_parser::{reader::*, Instrument};
 
 use crate::{broken_search::is_sample_absolute, types::{FlagBag, M8FstoErr}};
@@ -8,7 +8,7 @@ fn on_file_blob(flags : FlagBag, song_path: &Path, data: Vec<u8>) -> Result<(),
     let song = m8_file_parser::Song::read_from_reader(&mut reader)
         .map_err(|e| M8FstoErr::UnparseableM8File {
             path: song_path.to_path_buf(),
-            reason: format!("{:?}", e)
+            source: Arc::new(e)
         })?;
 
     let mut all_samples = HashSet::new();
@@ -67,7 +67,7 @@ fn on_file_blob(flags : FlagBag, song_path: &Path, data: Vec<u8>) -> Result<(),
             println!("Removing '{:?}'", pb);
             fs::remove_file(&pb)
                 .map_err(|e|
-                    M8FstoErr::FileRemovalFailure { path: pb.clone(), reason: format!("{:?}", e) })?;
+                    M8FstoErr::FileRemovalFailure { path: pb.clone(), source: Arc::new(e) })?;
         }
     }
 
@@ -78,7 +78,7 @@ fn on_file_blob(flags : FlagBag, song_path: &Path, data: Vec<u8>) -> Result<(),
 pub fn prune_bundle(flags: FlagBag, path : &str) -> Result<(), M8FstoErr> {
     let file_blob = fs::read(path)
         .map_err(|e|
-            M8FstoErr::CannotReadFile { path: PathBuf::from(path), reason: format!("{:?}", e) })?;
+            M8FstoErr::CannotReadFile { path: PathBuf::from(path), source: Arc::new(e) })?;
 
     let song_path = Path::new(path);
 
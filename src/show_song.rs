@@ -1,8 +1,8 @@
-use std::{collections::HashSet, fmt::Display, fs, path::PathBuf};
+use std::{collections::{BTreeMap, HashSet}, fmt::Display, fs, path::PathBuf, sync::Arc};
 
 use m8_file_parser::{param_gatherer::{Describable, ParameterGatherer}, reader::Reader, Instrument, Version};
 
-use crate::{types::M8FstoErr, ShowCommand, ShowTarget};
+use crate::{arrangement::{self, ArrangementView}, colorize::{self, Role, Theme}, eq_plot, fx_names::fx_stats, mod_curves::mod_curves, types::M8FstoErr, ShowCommand, ShowTarget};
 
 struct AsciiTherer<'a, 'writer> {
     write: &'a mut std::fmt::Formatter<'writer>,
@@ -89,9 +89,9 @@ impl<'a, 'writer> ParameterGatherer for AsciiTherer<'a, 'writer> {
     }
 }
 
-struct ElemDisplay<T> {
-    instr: T,
-    ver: Version
+pub(crate) struct ElemDisplay<T> {
+    pub(crate) instr: T,
+    pub(crate) ver: Version
 }
 
 impl<T : Describable> Display for ElemDisplay<T> {
@@ -101,7 +101,7 @@ impl<T : Describable> Display for ElemDisplay<T> {
     }
 }
 
-fn instrument_kind(i: &Instrument) -> &'static str {
+pub(crate) fn instrument_kind(i: &Instrument) -> &'static str {
     match i {
         Instrument::WavSynth(_) => "WavSynth",
         Instrument::MacroSynth(_) => "MacroSynth",
@@ -125,9 +125,17 @@ fn show_from_instrument(show: ShowCommand, w: &mut dyn std::io::Write, instr_eq:
             writeln!(w, "Kind    : {}", instrument_kind(&instr_eq.instrument)).map_err(|_| M8FstoErr::PrintError)?;
             Ok(())
         },
+        ShowTarget::Arrangement { track: _, rows: _ } => Ok(()),
+        ShowTarget::Groove { id: _ } => Ok(()),
+        ShowTarget::Scale { id: _ } => Ok(()),
+        ShowTarget::Midi => Ok(()),
+        ShowTarget::FxStats => Ok(()),
         ShowTarget::Chain { id: _ } => Ok(()),
         ShowTarget::Phrase { id: _} => Ok(()),
-        ShowTarget::Instrument { id: _ } => {
+        ShowTarget::Instrument { id: _, curves: true } => {
+            write!(w, "{}", mod_curves(&instr_eq.instrument, instr_eq.version)).map_err(|_| M8FstoErr::PrintError)
+        },
+        ShowTarget::Instrument { id: _, curves: false } => {
             write!(w, "{}", ElemDisplay {
                 instr: instr_eq.instrument,
                 ver: instr_eq.version
@@ -140,22 +148,142 @@ fn show_from_instrument(show: ShowCommand, w: &mut dyn std::io::Write, instr_eq:
             match instr_eq.eq {
                 None => writeln!(w, "No eq saved in the instrument").map_err(|_| M8FstoErr::PrintError),
                 Some(equ) => {
-                    write!(w, "{}", ElemDisplay {
+                    let plot = eq_plot::terminal_plot(
+                        instr_eq.instrument.name().unwrap_or("").to_string(),
+                        &equ,
+                        show.color.enabled());
+
+                    write!(w, "{}\n{}", ElemDisplay {
                         instr: equ,
                         ver: instr_eq.version
-                    }).map_err(|_| M8FstoErr::PrintError)
+                    }, plot).map_err(|_| M8FstoErr::PrintError)
                 }
             }
         },
     }
 }
 
+const NOTE_NAMES : [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"
+];
+
+/// Default groove of the M8, two ticks of 6
+fn is_default_groove(groove: &m8_file_parser::Groove) -> bool {
+    groove.steps.iter().enumerate().all(|(i, s)|
+        if i < 2 { *s == 6 } else { *s == arrangement::EMPTY })
+}
+
+/// Default scale of the M8, chromatic without any offset
+fn is_default_scale(scale: &m8_file_parser::Scale) -> bool {
+    scale.key == 0 &&
+        scale.notes.iter().all(|n| n.enable && n.semitones == 0 && n.cents == 0)
+}
+
+struct GrooveView<'a> {
+    groove: &'a m8_file_parser::Groove
+}
+
+impl<'a> Display for GrooveView<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "GROOVE {:02X}", self.groove.number)?;
+        writeln!(f)?;
+
+        for (i, ticks) in self.groove.steps.iter().enumerate() {
+            if *ticks == arrangement::EMPTY {
+                writeln!(f, "{:X} --", i)?;
+            } else {
+                writeln!(f, "{:X} {:02X}", i, ticks)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct ScaleView<'a> {
+    scale: &'a m8_file_parser::Scale
+}
+
+impl<'a> Display for ScaleView<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scale = self.scale;
+        writeln!(f, "SCALE {:02X} {}", scale.number, scale.name)?;
+        writeln!(f, "KEY {}", NOTE_NAMES[scale.key as usize % 12])?;
+        writeln!(f)?;
+        writeln!(f, "   N  EN  OFF CNT")?;
+
+        for (i, note) in scale.notes.iter().enumerate() {
+            let name = NOTE_NAMES[(i + scale.key as usize) % 12];
+            let enabled = if note.enable { "ON " } else { "OFF" };
+            writeln!(f, "{:X}  {:2} {} {:02X}  {:02X}", i, name, enabled, note.semitones, note.cents)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn channel_or_all(channel: u8) -> String {
+    if channel == 0 { "ALL".into() } else { format!("{}", channel) }
+}
+
+struct FxStatsView<'a> {
+    song: &'a m8_file_parser::Song
+}
+
+impl<'a> Display for FxStatsView<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut stats : Vec<_> = fx_stats(self.song).into_iter().collect();
+        stats.sort_by_key(|(_, c)| std::cmp::Reverse(c.phrases + c.tables));
+
+        writeln!(f, "FX    TOTAL PHRASES TABLES")?;
+        for (name, count) in stats {
+            writeln!(f, "{:<4} {:>6} {:>7} {:>6}", name, count.phrases + count.tables, count.phrases, count.tables)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct MidiSettingsView<'a> {
+    midi: &'a m8_file_parser::MidiSettings
+}
+
+impl<'a> Display for MidiSettingsView<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let m = self.midi;
+        writeln!(f, "SYNC")?;
+        writeln!(f, "  Receive sync         : {}", m.receive_sync)?;
+        writeln!(f, "  Receive transport    : {:02X}", m.receive_transport)?;
+        writeln!(f, "  Send sync            : {}", m.send_sync)?;
+        writeln!(f, "  Send transport       : {:02X}", m.send_transport)?;
+        writeln!(f, "RECORDING")?;
+        writeln!(f, "  Note channel         : {}", channel_or_all(m.record_note_channel))?;
+        writeln!(f, "  Note velocity        : {}", m.record_note_velocity)?;
+        writeln!(f, "  Delay/kill commands  : {:02X}", m.record_note_delay_kill_commands)?;
+        writeln!(f, "CONTROL")?;
+        writeln!(f, "  Control map channel  : {}", channel_or_all(m.control_map_channel))?;
+        writeln!(f, "  Song row cue channel : {}", channel_or_all(m.song_row_cue_channel))?;
+        writeln!(f, "TRACK INPUT")?;
+        writeln!(f, "  Program change       : {}", m.track_input_program_change)?;
+        writeln!(f, "  Mode                 : {:02X}", m.track_input_mode)?;
+        writeln!(f, "  TRK CHN INSTR")?;
+
+        for (track, (chan, instr)) in m.track_input_channel.iter()
+                                        .zip(m.track_input_intrument.iter())
+                                        .enumerate() {
+            writeln!(f, "  {}   {:3} {:02X}", track + 1, channel_or_all(*chan), instr)?;
+        }
+
+        Ok(())
+    }
+}
+
 /// Structure used to instantiate Display instance for song info
 struct SongInfoDisplay<'a> {
     song: &'a m8_file_parser::Song
 }
 
-struct InstrumentCounter {
+pub(crate) struct InstrumentCounter {
     pub wavsynth_count : usize,
     pub macrosynth_count : usize,
     pub fm_count : usize,
@@ -164,7 +292,8 @@ struct InstrumentCounter {
     pub external_count : usize,
     pub hypersynth_count: usize,
 
-    pub used_midi_channel : HashSet<u8>
+    /// Number of MIDI out and external instruments using each channel
+    pub used_midi_channel : BTreeMap<u8, usize>
 }
 
 impl InstrumentCounter {
@@ -178,6 +307,26 @@ impl InstrumentCounter {
             self.hypersynth_count
     }
 
+    /// Short description of the instrument kinds, like `2 WS, 5 SMP`
+    pub fn mix(&self) -> String {
+        let kinds = [
+            (self.wavsynth_count, "WS"),
+            (self.macrosynth_count, "MS"),
+            (self.sampler_count, "SMP"),
+            (self.fm_count, "FM"),
+            (self.hypersynth_count, "HS"),
+            (self.midi_count, "MIDI"),
+            (self.external_count, "EXT")
+        ];
+
+        kinds
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, kind)| format!("{} {}", count, kind))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
     pub fn count(mut self, instr: &m8_file_parser::Instrument) -> Self {
         match instr {
             Instrument::None => (),
@@ -186,13 +335,13 @@ impl InstrumentCounter {
             Instrument::Sampler(_) => self.sampler_count += 1,
             Instrument::MIDIOut(midiout) => {
                 self.midi_count += 1;
-                self.used_midi_channel.insert(midiout.channel);
+                *self.used_midi_channel.entry(midiout.channel).or_default() += 1;
             },
             Instrument::FMSynth(_) => self.fm_count += 1,
             Instrument::HyperSynth(_) => self.hypersynth_count += 1,
             Instrument::External(ext) => {
                 self.external_count += 1;
-                self.used_midi_channel.insert(ext.channel);
+                *self.used_midi_channel.entry(ext.channel).or_default() += 1;
             },
         };
 
@@ -226,17 +375,26 @@ impl Display for InstrumentCounter {
         writeln!(f, "            MIDI out : {}", self.midi_count)?;
         writeln!(f, "           Ext instr : {}", self.external_count)?;
 
-        let midi_vec : Vec<_> = self.used_midi_channel.iter().map(|c| format!("{}", c)).collect();
+        let midi_vec : Vec<_> = self.used_midi_channel.keys().map(|c| format!("{}", c)).collect();
         write!(f, "  used midi channels : {}", midi_vec.join(", "))?;
         Ok(())
     }
 }
 
-impl<'a> Display for SongInfoDisplay<'a> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = self.song;
-
+/// Counts displayed by `show info`
+pub(crate) struct SongStats {
+    pub instruments: InstrumentCounter,
+    pub distinct_samples: usize,
+    pub non_flat_eqs: usize,
+    pub non_empty_tables: usize,
+    pub used_chains: usize,
+    pub used_phrases: usize,
+    pub non_default_grooves: usize,
+    pub non_default_scales: usize
+}
 
+impl SongStats {
+    pub fn of(s: &m8_file_parser::Song) -> Self {
         let samples : HashSet<_> = s.instruments
             .iter()
             .filter_map(|i|
@@ -246,38 +404,60 @@ impl<'a> Display for SongInfoDisplay<'a> {
                 })
             .collect();
 
-        writeln!(f, "Version              : {}", s.version)?;
-        writeln!(f, "Name                 : {}", s.name)?;
-
-        let instr_count = s.instruments.iter()
+        let instruments = s.instruments.iter()
             .fold(
                 InstrumentCounter::default(),
                 |acc, i| acc.count(i));
 
-        writeln!(f, "{}", instr_count)?;
-
-        writeln!(f, "Distinct samples     : {}", samples.len())?;
-
-        let eqs = s.eqs.iter().filter(|t| !t.is_empty()).count();
-        writeln!(f, "Non flat EQs         : {}", eqs)?;
-
-        let tables = s.tables.iter().filter(|t| !t.is_empty()).count();
-        writeln!(f, "Non empty table      : {}", tables)?;
+        Self {
+            instruments,
+            distinct_samples: samples.len(),
+            non_flat_eqs: s.eqs.iter().filter(|t| !t.is_empty()).count(),
+            non_empty_tables: s.tables.iter().filter(|t| !t.is_empty()).count(),
+            used_chains: s.chains.iter().filter(|c| !c.is_empty()).count(),
+            used_phrases: s.phrases.iter().filter(|c| !c.is_empty()).count(),
+            non_default_grooves: s.grooves.iter().filter(|g| !is_default_groove(g)).count(),
+            non_default_scales: s.scales.iter().filter(|sc| !is_default_scale(sc)).count()
+        }
+    }
+}
 
-        let chains = s.chains.iter().filter(|c| !c.is_empty()).count();
-        writeln!(f, "Used chains          : {}", chains)?;
+impl<'a> Display for SongInfoDisplay<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = self.song;
+        let stats = SongStats::of(s);
 
-        let phrases = s.phrases.iter().filter(|c| !c.is_empty()).count();
-        writeln!(f, "Used phrases         : {}", phrases)?;
+        writeln!(f, "Version              : {}", s.version)?;
+        writeln!(f, "Name                 : {}", s.name)?;
+        writeln!(f, "{}", stats.instruments)?;
+        writeln!(f, "Distinct samples     : {}", stats.distinct_samples)?;
+        writeln!(f, "Non flat EQs         : {}", stats.non_flat_eqs)?;
+        writeln!(f, "Non empty table      : {}", stats.non_empty_tables)?;
+        writeln!(f, "Used chains          : {}", stats.used_chains)?;
+        writeln!(f, "Used phrases         : {}", stats.used_phrases)?;
+        writeln!(f, "Non default grooves  : {}", stats.non_default_grooves)?;
+        writeln!(f, "Non default scales   : {}", stats.non_default_scales)?;
 
         Ok(())
     }
 }
 
+/// Print a rendered view, colored when a theme is given
+fn write_view(w: &mut dyn std::io::Write, view: String, columns: &[Role], theme: &Option<Theme>) -> Result<(), M8FstoErr> {
+    let text = match theme {
+        None => view,
+        Some(theme) => colorize::paint(&view, columns, theme)
+    };
+
+    writeln!(w, "{}", text).map_err(|_| M8FstoErr::PrintError)
+}
+
 fn show_from_song(show: ShowCommand, w: &mut dyn std::io::Write, song: m8_file_parser::Song) -> Result<(), M8FstoErr> {
+    let theme = colorize::output_theme(show.color, show.theme.as_deref())?;
+
     match show.show_command {
         ShowTarget::Song => {
-            writeln!(w, "{}", song.song).map_err(|_| M8FstoErr::PrintError)
+            write_view(w, song.song.to_string(), colorize::SONG_COLUMNS, &theme)
         }
         ShowTarget::Effects => {
             write!(w, "{}", ElemDisplay {
@@ -294,13 +474,24 @@ fn show_from_song(show: ShowCommand, w: &mut dyn std::io::Write, song: m8_file_p
         ShowTarget::Info => {
             writeln!(w, "{}", SongInfoDisplay { song: &song }).map_err(|_| M8FstoErr::PrintError)
         }
+        ShowTarget::Arrangement { track, rows } => {
+            let tracks = match track {
+                None => (0 .. arrangement::TRACK_COUNT).collect(),
+                Some(t) => vec![t as usize - 1]
+            };
+
+            let rows = rows.unwrap_or_else(||
+                0 .. arrangement::last_used_row(&song).map_or(0, |r| r + 1));
+
+            write!(w, "{}", ArrangementView { song: &song, tracks, rows }).map_err(|_| M8FstoErr::PrintError)
+        }
         ShowTarget::Chain { id } => {
-            writeln!(w, "{}", song.chains[id]).map_err(|_| M8FstoErr::PrintError)
+            write_view(w, song.chains[id].to_string(), colorize::CHAIN_COLUMNS, &theme)
         }
         ShowTarget::Phrase { id } => {
-            writeln!(w, "{}", song.phrase_view(id)).map_err(|_| M8FstoErr::PrintError)
+            write_view(w, song.phrase_view(id).to_string(), colorize::PHRASE_COLUMNS, &theme)
         },
-        ShowTarget::Instrument { id: None} => {
+        ShowTarget::Instrument { id: None, curves: _ } => {
             for (ix, instr) in song.instruments.iter().enumerate() {
                 match instr {
                     Instrument::None => {}
@@ -329,7 +520,10 @@ fn show_from_song(show: ShowCommand, w: &mut dyn std::io::Write, song: m8_file_p
             };
             Ok(())
         },
-        ShowTarget::Instrument { id: Some(id)} => {
+        ShowTarget::Instrument { id: Some(id), curves: true } => {
+            write!(w, "{}", mod_curves(&song.instruments[id], song.version)).map_err(|_| M8FstoErr::PrintError)
+        },
+        ShowTarget::Instrument { id: Some(id), curves: false } => {
             write!(w, "{}", ElemDisplay {
                 instr: song.instruments[id].clone(),
                 ver: song.version
@@ -339,16 +533,32 @@ fn show_from_song(show: ShowCommand, w: &mut dyn std::io::Write, song: m8_file_p
             writeln!(w, "Please select table number").map_err(|_| M8FstoErr::PrintError)
         },
         ShowTarget::Table { id: Some(id) } => {
-            writeln!(w, "{}", song.table_view(id)).map_err(|_| M8FstoErr::PrintError)
+            write_view(w, song.table_view(id).to_string(), colorize::TABLE_COLUMNS, &theme)
         },
+        ShowTarget::Groove { id } => {
+            let groove = song.grooves.get(id).ok_or(M8FstoErr::UnknownElement { kind: "groove", id })?;
+            write!(w, "{}", GrooveView { groove }).map_err(|_| M8FstoErr::PrintError)
+        }
+        ShowTarget::Scale { id } => {
+            let scale = song.scales.get(id).ok_or(M8FstoErr::UnknownElement { kind: "scale", id })?;
+            write!(w, "{}", ScaleView { scale }).map_err(|_| M8FstoErr::PrintError)
+        }
+        ShowTarget::Midi => {
+            write!(w, "{}", MidiSettingsView { midi: &song.midi_settings }).map_err(|_| M8FstoErr::PrintError)
+        }
+        ShowTarget::FxStats => {
+            write!(w, "{}", FxStatsView { song: &song }).map_err(|_| M8FstoErr::PrintError)
+        }
         ShowTarget::Eq { id: None } => {
             writeln!(w, "Please select eq number").map_err(|_| M8FstoErr::PrintError)
         }
         ShowTarget::Eq { id: Some(id) } => {
-            write!(w, "{}", ElemDisplay {
-                instr: song.eqs[id].clone(),
+            let eq = song.eqs.get(id).ok_or(M8FstoErr::UnknownElement { kind: "EQ", id })?;
+            let plot = eq_plot::terminal_plot(format!("EQ {:02X}", id), eq, show.color.enabled());
+            write!(w, "{}\n{}", ElemDisplay {
+                instr: eq.clone(),
                 ver: song.version
-            }).map_err(|_| M8FstoErr::PrintError)
+            }, plot).map_err(|_| M8FstoErr::PrintError)
         },
     }
 }
@@ -357,7 +567,7 @@ pub fn show_element(show: ShowCommand, w: &mut dyn std::io::Write) -> Result<(),
     let song_path = PathBuf::from(show.file.clone());
     let file_blob = fs::read(song_path.clone())
         .map_err(|e|
-            M8FstoErr::CannotReadFile { path: song_path.clone(), reason: format!("{:?}", e) })?;
+            M8FstoErr::CannotReadFile { path: song_path.clone(), source: Arc::new(e) })?;
 
     let mut reader = Reader::new(file_blob);
 
@@ -370,7 +580,7 @@ pub fn show_element(show: ShowCommand, w: &mut dyn std::io::Write) -> Result<(),
                 Err(_) => {
                     Err(M8FstoErr::UnparseableM8File {
                         path: song_path,
-                        reason: format!("{:?}", e)
+                        source: Arc::new(e)
                     })
                 }
             }
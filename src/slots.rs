@@ -0,0 +1,121 @@
+use m8_file_parser::{Equ, Instrument, Song};
+
+use crate::{arrangement::{SONG_ROW_COUNT, TRACK_COUNT, EMPTY}, types::M8FstoErr};
+
+/// EQ index of an instrument without EQ
+pub const NO_EQ : u8 = 0xFF;
+
+/// Phrases which are empty and not referenced by any chain, they
+/// can receive new content.
+pub fn free_phrases(song: &Song) -> Vec<usize> {
+    let mut referenced = vec![false; song.phrases.len()];
+    for chain in song.chains.iter() {
+        for step in chain.steps.iter() {
+            if let Some(r) = referenced.get_mut(step.phrase as usize) {
+                *r = true;
+            }
+        }
+    }
+
+    song.phrases
+        .iter()
+        .enumerate()
+        .filter(|(i, p)| p.is_empty() && !referenced[*i])
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Chains which are empty and not referenced in the song grid, they
+/// can receive new content.
+pub fn free_chains(song: &Song) -> Vec<usize> {
+    let mut referenced = vec![false; song.chains.len()];
+    for cell in song.song.steps.iter().take(SONG_ROW_COUNT * TRACK_COUNT) {
+        if *cell == EMPTY { continue }
+        if let Some(r) = referenced.get_mut(*cell as usize) {
+            *r = true;
+        }
+    }
+
+    song.chains
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| c.is_empty() && !referenced[*i])
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Take the first `count` free slots, or report how many are missing
+pub fn take_slots(free: &[usize], count: usize, kind: &'static str) -> Result<Vec<usize>, M8FstoErr> {
+    if free.len() < count {
+        Err(M8FstoErr::NoFreeSlot { kind, needed: count, available: free.len() })
+    } else {
+        Ok(free[.. count].to_vec())
+    }
+}
+
+/// Instruments which are empty, with an empty table, and not used
+/// by any phrase, they can receive new content.
+pub fn free_instruments(song: &Song) -> Vec<usize> {
+    let mut referenced = vec![false; song.instruments.len()];
+    for phrase in song.phrases.iter() {
+        for step in phrase.steps.iter() {
+            if let Some(r) = referenced.get_mut(step.instrument as usize) {
+                *r = true;
+            }
+        }
+    }
+
+    song.instruments
+        .iter()
+        .enumerate()
+        .filter(|(i, instr)| matches!(instr, Instrument::None) && !referenced[*i])
+        .filter(|(i, _)| song.tables.get(*i).is_none_or(|t| t.is_empty()))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// EQs which are flat and not used by any instrument
+pub fn free_eqs(song: &Song) -> Vec<usize> {
+    let mut referenced = vec![false; song.eqs.len()];
+    for instr in song.instruments.iter() {
+        if let Some(r) = instr.equ().and_then(|eq| referenced.get_mut(eq as usize)) {
+            *r = true;
+        }
+    }
+
+    song.eqs
+        .iter()
+        .enumerate()
+        .filter(|(i, eq)| eq.is_empty() && !referenced[*i])
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Slot receiving a copied EQ, an identical EQ of the song is reused,
+/// otherwise a free slot is taken and filled.
+pub fn place_eq(song: &mut Song, eq: Equ) -> Result<usize, M8FstoErr> {
+    let slot = match song.eqs.iter().position(|e| *e == eq) {
+        Some(existing) => existing,
+        None => take_slots(&free_eqs(song), 1, "EQ")?[0]
+    };
+
+    song.eqs[slot] = eq;
+    Ok(slot)
+}
+
+/// Give an instrument the EQ it comes with. Missing and flat EQs don't
+/// need a slot, the instrument gets `NO_EQ`, other EQs are placed with
+/// `place_eq`. Returns the EQ slot used, if any.
+pub fn attach_eq(song: &mut Song, instrument: &mut Instrument, eq: Option<Equ>) -> Result<Option<usize>, M8FstoErr> {
+    match eq {
+        Some(eq) if !eq.is_empty() => {
+            let slot = place_eq(song, eq)?;
+            instrument.set_eq(slot as u8);
+            Ok(Some(slot))
+        }
+        _ => {
+            instrument.set_eq(NO_EQ);
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use std::{fs, path::Path, sync::Arc};
+
+use m8_file_parser::{reader::Reader, Song};
+
+use crate::types::M8FstoErr;
+
+/// Read and parse a song file, also returning the raw file content
+/// which is needed to write the song back.
+pub fn read_song_file(path: &Path) -> Result<(Song, Vec<u8>), M8FstoErr> {
+    let file_blob = fs::read(path)
+        .map_err(|e| M8FstoErr::CannotReadFile {
+            path: path.to_path_buf(),
+            source: Arc::new(e)
+        })?;
+
+    let mut reader = Reader::new(file_blob.clone());
+    let song = Song::read_from_reader(&mut reader)
+        .map_err(|e| M8FstoErr::UnparseableM8File {
+            path: path.to_path_buf(),
+            source: Arc::new(e)
+        })?;
+
+    Ok((song, file_blob))
+}
@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+
+use m8_file_parser::Song;
+
+use crate::{ignore_rules::IgnoreRules, song_file::read_song_file, types::{M8FstoErr, Outcome}};
+
+/// Song files under a directory, in path order
+pub(crate) fn songs_under_dir(dir: &Path, ignore: &IgnoreRules) -> Result<Vec<PathBuf>, M8FstoErr> {
+    let pattern = dir.join("**").join("*.m8s")
+        .to_str()
+        .ok_or(M8FstoErr::InvalidPath { reason: "Invalid pattern".into() })?
+        .to_string();
+
+    let files = glob::glob(&pattern)
+        .map_err(|e| M8FstoErr::InvalidSearchPattern { pattern: format!("{:?}", e) })?;
+
+    Ok(files
+        .filter_map(|entry| entry.ok())
+        .filter(|path| !ignore.is_ignored(path))
+        .collect())
+}
+
+/// Call `on_song` on every song found in a list of directories and/or
+/// song paths, the current directory being used when the list is empty.
+/// Errors on a song don't stop the scan, they are reported at the end.
+pub fn scan_songs(
+    cwd: &Path,
+    ignore: &IgnoreRules,
+    paths: &[String],
+    mut on_song: impl FnMut(&Path, Song) -> Result<Outcome, M8FstoErr>) -> Result<Outcome, M8FstoErr> {
+
+    let mut songs = vec![];
+    let mut errors = vec![];
+
+    if paths.is_empty() {
+        songs.extend(songs_under_dir(cwd, ignore)?);
+    }
+
+    for path in paths {
+        let path_buf = PathBuf::from(path);
+        if path_buf.is_dir() {
+            match songs_under_dir(&path_buf, ignore) {
+                Ok(found) => songs.extend(found),
+                Err(e) => errors.push(e)
+            }
+        } else if path_buf.is_file() && path.ends_with(".m8s") {
+            songs.push(path_buf);
+        } else {
+            errors.push(M8FstoErr::InvalidSearchPattern { pattern: path.to_string() });
+        }
+    }
+
+    let mut outcome = Outcome::Clean;
+    for song_path in songs {
+        match read_song_file(&song_path) {
+            Ok((song, _)) if ignore.is_version_ignored(&song.version) => {}
+            Ok((song, _)) => match on_song(&song_path, song) {
+                Ok(o) => outcome = outcome.merge(o),
+                Err(e) => errors.push(e)
+            }
+            Err(e) => errors.push(e)
+        }
+    }
+
+    M8FstoErr::from_file_errors(errors).map(|()| outcome)
+}
@@ -1,4 +1,6 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{error::Error, fmt::Display, path::PathBuf, sync::Arc};
+
+use m8_file_parser::reader::ParseError;
 
 /// Some standard flags used acrross various commands.
 #[derive(Clone, Copy)]
@@ -16,23 +18,90 @@ pub struct FlagBag {
 
 }
 
+/// Result of a command that ran to completion
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Nothing special to report
+    Clean,
+
+    /// The command found what it was searching for, like
+    /// broken songs for `broken-search`.
+    Findings
+}
+
+impl Outcome {
+    pub fn findings_if(found: bool) -> Self {
+        if found { Outcome::Findings } else { Outcome::Clean }
+    }
+
+    /// Merge the outcome of two sub-tasks.
+    pub fn merge(self, other: Outcome) -> Self {
+        Outcome::findings_if(self == Outcome::Findings || other == Outcome::Findings)
+    }
+}
+
+/// Process exit codes, usable by scripts or CI checks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Everything went fine, nothing to report
+    Success = 0,
+
+    /// Command went fine, but has found something (broken songs...)
+    Findings = 1,
+
+    /// Some files could not be processed, but the command
+    /// went through the others.
+    PartialFailure = 2,
+
+    /// The command could not be performed.
+    Fatal = 3
+}
+
+impl From<ExitStatus> for std::process::ExitCode {
+    fn from(value: ExitStatus) -> Self {
+        std::process::ExitCode::from(value as u8)
+    }
+}
+
+impl From<Outcome> for ExitStatus {
+    fn from(value: Outcome) -> Self {
+        match value {
+            Outcome::Clean => ExitStatus::Success,
+            Outcome::Findings => ExitStatus::Findings
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum M8FstoErr {
-    UnparseableM8File { path: PathBuf, reason: String },
+    UnparseableM8File { path: PathBuf, source: Arc<ParseError> },
     InvalidSearchPattern { pattern: String },
-    CannotReadFile { path: PathBuf, reason: String },
-    SampleCopyError { path: PathBuf, to: PathBuf, reason: String },
+    CannotReadFile { path: PathBuf, source: Arc<std::io::Error> },
+    SampleCopyError { path: PathBuf, to: PathBuf, source: Arc<std::io::Error> },
     SongSerializationError { destination: String, reason: String },
+    FileWriteError { path: PathBuf, source: Arc<std::io::Error> },
     MissingSample { instr: usize, path: PathBuf },
     MultiErrs { inner: Vec<M8FstoErr> },
-    FolderCreationError { path: PathBuf, reason: String },
+    FolderCreationError { path: PathBuf, source: Arc<std::io::Error> },
     SampleInBundleNotRelative {
         sample_path: String,
         instrument: usize
     },
-    FileRemovalFailure { path: PathBuf, reason: String },
+    FileRemovalFailure { path: PathBuf, source: Arc<std::io::Error> },
     InvalidPath { reason: String },
-    RenameFailure { path: String },
+    RenameFailure { path: PathBuf, source: Arc<std::io::Error> },
+    IncompatibleSongs { reason: String },
+    UnparseableMidiFile { path: PathBuf, source: midly::Error },
+    UnsupportedMidiFile { path: PathBuf, reason: String },
+    NoFreeSlot { kind: &'static str, needed: usize, available: usize },
+    SlotNotEmpty { kind: &'static str, id: usize },
+    InvalidDump { reason: String },
+    InvalidTheme { path: PathBuf, reason: String },
+    TerminalError { source: Arc<std::io::Error> },
+    UnknownElement { kind: &'static str, id: usize },
+    InstrumentRoundTrip { path: PathBuf, reason: String },
+    SampleNameClash { name: String, first: PathBuf, second: PathBuf },
+    EqSelectionOnInstrument { path: PathBuf },
     PrintError
 }
 
@@ -51,10 +120,30 @@ impl M8FstoErr {
                 Self::MultiErrs { inner }
             }
             _ => {
-                Self::MultiErrs { inner: vec![self, other] }
+                M8FstoErr::MultiErrs { inner: vec![self] }.combine(other)
             }
         }
     }
+
+    /// Gather the errors collected while processing many files. A lone
+    /// error is returned as is, several errors are kept in a `MultiErrs`
+    /// to indicate that the processing went through the other files.
+    pub fn from_file_errors(errors: Vec<M8FstoErr>) -> Result<(), M8FstoErr> {
+        errors
+            .into_iter()
+            .fold(None, combine)
+            .map_or(Ok(()), Err)
+    }
+
+    /// Exit status to use when the command fails with this error.
+    /// Multiple errors are only collected when a command keeps going
+    /// after a failure on a file, so we consider it partial.
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            M8FstoErr::MultiErrs { .. } => ExitStatus::PartialFailure,
+            _ => ExitStatus::Fatal
+        }
+    }
 }
 
 pub fn combine(err: Option<M8FstoErr>, other: M8FstoErr) -> Option<M8FstoErr> {
@@ -70,14 +159,14 @@ impl Display for M8FstoErr {
             M8FstoErr::PrintError => {
                 writeln!(f, "Printing error?")
             },
-            M8FstoErr::UnparseableM8File { path, reason } => {
-                writeln!(f, "Can't parse M8 file '{:?}' : {}", path.as_path(), reason)
+            M8FstoErr::UnparseableM8File { path, source: _ } => {
+                writeln!(f, "Can't parse M8 file '{:?}'", path.as_path())
             }
             M8FstoErr::InvalidSearchPattern { pattern } => {
                 writeln!(f, "Invalid search pattern '{}'", pattern)
             },
-            M8FstoErr::CannotReadFile { path, reason } => {
-                writeln!(f, "Cannot read file '{:?}' : {}", path, reason)
+            M8FstoErr::CannotReadFile { path, source: _ } => {
+                writeln!(f, "Cannot read file '{:?}'", path)
             },
             M8FstoErr::MultiErrs { inner } => {
                 for i in inner.iter() {
@@ -88,27 +177,124 @@ impl Display for M8FstoErr {
             M8FstoErr::MissingSample { instr, path } => {
                 writeln!(f, "Missing sample '{:?}' for instrument {:02X}", path, instr)
             }
-            M8FstoErr::SampleCopyError { path, to , reason } => {
-                writeln!(f, "Cannot copy file '{:?}' to '{:?}' : {}", path, to, reason)
+            M8FstoErr::SampleCopyError { path, to , source: _ } => {
+                writeln!(f, "Cannot copy file '{:?}' to '{:?}'", path, to)
             }
             M8FstoErr::SongSerializationError { destination, reason } => {
                 writeln!(f, "Error while writing song \"{}\": {}", destination, reason)
             }
+            M8FstoErr::FileWriteError { path, source: _ } => {
+                writeln!(f, "Cannot write file '{:?}'", path)
+            }
             M8FstoErr::SampleInBundleNotRelative { sample_path, instrument } => {
                 writeln!(f, "The M8 song has non-relative sample \"{}\" for instrument {:02X}", sample_path, instrument)
             }
-            M8FstoErr::FolderCreationError { path, reason} => {
-                writeln!(f, "Cannot create folder '{:?}' for bundling : {}", path, reason)
+            M8FstoErr::FolderCreationError { path, source: _ } => {
+                writeln!(f, "Cannot create folder '{:?}' for bundling", path)
             }
-            M8FstoErr::FileRemovalFailure { path, reason} => {
-                writeln!(f, "Cannot remove file {:?} : '{}'", path, reason)
+            M8FstoErr::FileRemovalFailure { path, source: _ } => {
+                writeln!(f, "Cannot remove file {:?}", path)
             }
             M8FstoErr::InvalidPath { reason }=> {
                 writeln!(f, "Invalid path {}", reason)
             }
-            M8FstoErr::RenameFailure { path } => {
+            M8FstoErr::IncompatibleSongs { reason } => {
+                writeln!(f, "Incompatible songs: {}", reason)
+            }
+            M8FstoErr::UnparseableMidiFile { path, source: _ } => {
+                writeln!(f, "Can't parse MIDI file '{:?}'", path)
+            }
+            M8FstoErr::UnsupportedMidiFile { path, reason } => {
+                writeln!(f, "Unsupported MIDI file '{:?}' : {}", path, reason)
+            }
+            M8FstoErr::NoFreeSlot { kind, needed, available } => {
+                writeln!(f, "Not enough free {} slots, {} needed but only {} available", kind, needed, available)
+            }
+            M8FstoErr::SlotNotEmpty { kind, id } => {
+                writeln!(f, "{} {:02X} is not empty", kind, id)
+            }
+            M8FstoErr::InvalidDump { reason } => {
+                writeln!(f, "Invalid JSON dump: {}", reason)
+            }
+            M8FstoErr::InvalidTheme { path, reason } => {
+                writeln!(f, "Invalid theme file {:?}: {}", path, reason)
+            }
+            M8FstoErr::RenameFailure { path, source: _ } => {
                 writeln!(f, "Cannot rename file or folder \"{:?}\"", path)
             }
+            M8FstoErr::TerminalError { source: _ } => {
+                writeln!(f, "Cannot use the terminal")
+            }
+            M8FstoErr::UnknownElement { kind, id } => {
+                writeln!(f, "No {} {:02X} in the file", kind, id)
+            }
+            M8FstoErr::InstrumentRoundTrip { path, reason } => {
+                writeln!(f, "Instrument file {:?} would not load back: {}", path, reason)
+            }
+            M8FstoErr::SampleNameClash { name, first, second } => {
+                writeln!(f, "Samples {:?} and {:?} would both be copied as \"{}\"", first, second, name)
+            }
+            M8FstoErr::EqSelectionOnInstrument { path } => {
+                writeln!(f, "{:?} is an instrument file, --eq and --instrument only apply to songs", path)
+            }
+        }
+    }
+}
+
+impl Error for M8FstoErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            M8FstoErr::UnparseableM8File { source, .. } => Some(source.as_ref()),
+            M8FstoErr::CannotReadFile { source, .. } => Some(source.as_ref()),
+            M8FstoErr::SampleCopyError { source, .. } => Some(source.as_ref()),
+            M8FstoErr::FileWriteError { source, .. } => Some(source.as_ref()),
+            M8FstoErr::FolderCreationError { source, .. } => Some(source.as_ref()),
+            M8FstoErr::FileRemovalFailure { source, .. } => Some(source.as_ref()),
+            M8FstoErr::RenameFailure { source, .. } => Some(source.as_ref()),
+            M8FstoErr::TerminalError { source } => Some(source.as_ref()),
+            M8FstoErr::UnparseableMidiFile { source, .. } => Some(source),
+            M8FstoErr::MultiErrs { .. }
+            | M8FstoErr::InvalidSearchPattern { .. }
+            | M8FstoErr::SongSerializationError { .. }
+            | M8FstoErr::MissingSample { .. }
+            | M8FstoErr::SampleInBundleNotRelative { .. }
+            | M8FstoErr::InvalidPath { .. }
+            | M8FstoErr::IncompatibleSongs { .. }
+            | M8FstoErr::UnsupportedMidiFile { .. }
+            | M8FstoErr::NoFreeSlot { .. }
+            | M8FstoErr::SlotNotEmpty { .. }
+            | M8FstoErr::InvalidDump { .. }
+            | M8FstoErr::InvalidTheme { .. }
+            | M8FstoErr::UnknownElement { .. }
+            | M8FstoErr::InstrumentRoundTrip { .. }
+            | M8FstoErr::SampleNameClash { .. }
+            | M8FstoErr::EqSelectionOnInstrument { .. }
+            | M8FstoErr::PrintError => None
+        }
+    }
+}
+
+/// Display an error followed by its chain of causes, one per line.
+pub struct ErrorReport<'a>(pub &'a M8FstoErr);
+
+impl<'a> Display for ErrorReport<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            M8FstoErr::MultiErrs { inner } => {
+                for i in inner.iter() {
+                    ErrorReport(i).fmt(f)?
+                }
+                Ok(())
+            }
+            err => {
+                err.fmt(f)?;
+                let mut cause = err.source();
+                while let Some(c) = cause {
+                    writeln!(f, "  caused by: {}", c)?;
+                    cause = c.source();
+                }
+                Ok(())
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,63 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+
+use m8_file_parser::{writer::Writer, Song};
+
+use crate::{ignore_rules::IgnoreRules, song_scan::scan_songs, types::{M8FstoErr, Outcome}};
+
+/// Commands only reading songs, working with every parsed song
+const READ_COMMANDS : &str = "ls-sample, grep-*, broken-search, lint, catalog, show, diff, dump, export-midi, extract-instrument";
+
+/// Commands writing songs, requiring a song the parser can write back
+const WRITE_COMMANDS : &str = "mv, bundle, merge, load, import-midi, import-instrument, copy-chain, compact";
+
+/// Check if the parser can write the song back
+fn can_write(song: &Song, path: &Path) -> bool {
+    fs::read(path).is_ok_and(|blob| song.write(&mut Writer::new(blob)).is_ok())
+}
+
+/// Songs made with one firmware version
+struct VersionGroup {
+    name: String,
+
+    /// Song path relative to the root, and if the song can be written
+    songs: Vec<(PathBuf, bool)>
+}
+
+/// Group the songs under a root folder by firmware version, flagging
+/// the songs which can be read but not written back.
+pub fn versions(root: &Path, ignore: &IgnoreRules) -> Result<Outcome, M8FstoErr> {
+    let mut by_version : BTreeMap<(u8, u8, u8), VersionGroup> = BTreeMap::new();
+
+    let scan = scan_songs(root, ignore, &[], |path, song| {
+        let v = &song.version;
+        let writable = can_write(&song, path);
+        let rel_path = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+
+        by_version
+            .entry((v.major, v.minor, v.patch))
+            .or_insert_with(|| VersionGroup { name: v.to_string(), songs: vec![] })
+            .songs
+            .push((rel_path, writable));
+
+        Ok(Outcome::findings_if(!writable))
+    });
+
+    for group in by_version.values().rev() {
+        let read_only = group.songs.iter().filter(|(_, w)| !w).count();
+        println!("== {} : {} songs, {} read only", group.name, group.songs.len(), read_only);
+
+        for (path, writable) in &group.songs {
+            if *writable {
+                println!("  {}", path.display());
+            } else {
+                println!("  {} (read only)", path.display());
+            }
+        }
+    }
+
+    println!();
+    println!("All songs          : {}", READ_COMMANDS);
+    println!("Writable songs only: {}", WRITE_COMMANDS);
+
+    scan
+}